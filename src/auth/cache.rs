@@ -0,0 +1,288 @@
+//! Authentication result caching.
+
+use std::{collections::HashMap,
+          sync::Mutex,
+          time::{Duration, Instant, SystemTime}};
+
+use async_trait::async_trait;
+use tracing::trace;
+
+use super::{Authenticator, Challenge, Credentials, Error, Principal};
+use crate::{http::digest, metrics, proxy::Flow};
+
+/// Map of values expiring after TTL given on insert, holding up to capacity entries. Once full, expired entries are
+/// pruned no sooner than earliest of them expires, and new entries are dropped while all held ones are live.
+#[derive(Debug)]
+pub(crate) struct TtlMap<V> {
+    entries: HashMap<String, (Instant, V)>,
+    capacity: usize,
+
+    /// Earliest expiry among entries as of last prune, before which pruning would find nothing.
+    next_expiry: Option<Instant>,
+}
+
+impl<V> TtlMap<V>
+where
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            next_expiry: None,
+        }
+    }
+
+    /// Live value for key.
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.entries
+            .get(key)
+            .filter(|(expires_at, _)| *expires_at > Instant::now())
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Store value for TTL, returning whether there was room for it.
+    pub fn insert(&mut self, key: String, value: V, ttl: Duration) -> bool {
+        let now = Instant::now();
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            if self.next_expiry.map_or(true, |next| next <= now) {
+                self.entries.retain(|_, (expires_at, _)| *expires_at > now);
+                self.next_expiry = self
+                    .entries
+                    .values()
+                    .map(|(expires_at, _)| *expires_at)
+                    .min();
+            }
+            if self.entries.len() >= self.capacity {
+                trace!("cache full, not storing {key}");
+                return false;
+            }
+        }
+
+        let expires_at = now + ttl;
+        self.next_expiry = self.next_expiry.map(|next| next.min(expires_at));
+        self.entries.insert(key, (expires_at, value));
+
+        true
+    }
+}
+
+/// Wrapper caching identities accepted by inner authenticator, keyed by digest of client address and credentials, so
+/// that expensive verification such as bcrypt or remote lookup runs once per TTL. Failures are never cached.
+///
+/// Must not wrap authenticators whose credentials are single-use, such as [`Digest`](super::Digest), as cached
+/// results would bypass their replay protection.
+#[derive(Debug)]
+pub struct Cached<A> {
+    inner: A,
+    ttl: Duration,
+    cache: Mutex<TtlMap<Principal>>,
+}
+
+impl<A> Cached<A>
+where
+    A: Authenticator,
+{
+    /// Create new cache over authenticator, keeping up to 10 000 results for 1 minute.
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            ttl: Duration::from_secs(60),
+            cache: Mutex::new(TtlMap::new(10_000)),
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(flow: &Flow, credentials: &Credentials) -> String {
+        digest(
+            format!(
+                "{ip}\0{scheme}\0{credentials}",
+                ip = flow.client().ip(),
+                scheme = credentials.scheme().to_lowercase(),
+                credentials = credentials.credentials()
+            )
+            .as_bytes(),
+        )
+    }
+
+    fn store(&self, key: String, principal: Principal) {
+        let mut ttl = self.ttl;
+
+        // Never trust identity beyond its own expiry
+        if let Some(exp) = principal.expires_at {
+            ttl = ttl.min(exp.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+
+        self.cache.lock().unwrap().insert(key, principal, ttl);
+    }
+}
+
+#[async_trait]
+impl<A> Authenticator for Cached<A>
+where
+    A: Authenticator + Send + Sync,
+{
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        // Nothing to key on; let inner authenticator decide every time
+        let credentials = match credentials {
+            Some(credentials) => credentials,
+            None => return self.inner.authenticate(flow, None).await,
+        };
+
+        let key = Self::key(flow, credentials);
+        let cached = self.cache.lock().unwrap().get(&key);
+        if let Some(principal) = cached {
+            trace!("authentication cache hit");
+            metrics::AUTH_CACHE_HIT_COUNTER.increment(1);
+            return Ok(principal);
+        }
+
+        metrics::AUTH_CACHE_MISS_COUNTER.increment(1);
+        let principal = self.inner.authenticate(flow, Some(credentials)).await?;
+        self.store(key, principal.clone());
+
+        Ok(principal)
+    }
+
+    fn challenge(&self) -> Option<Challenge> {
+        self.inner.challenge()
+    }
+
+    fn challenges(&self) -> Vec<Challenge> {
+        self.inner.challenges()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering},
+                     Arc},
+              time::{Duration, SystemTime}};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    use super::{Cached, TtlMap};
+    use crate::{auth::{tests::flow, Authenticator, Challenge, Credentials, Error, HTTPBasic,
+                       Principal},
+                proxy::{Flow, Proxy}};
+
+    /// Basic authenticator counting verifications.
+    #[derive(Debug)]
+    struct Counting {
+        inner: HTTPBasic,
+        calls: Arc<AtomicUsize>,
+        expires_at: Option<SystemTime>,
+    }
+
+    #[async_trait]
+    impl Authenticator for Counting {
+        async fn authenticate(
+            &self,
+            flow: &Flow,
+            credentials: Option<&Credentials>,
+        ) -> Result<Principal, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut principal = self.inner.authenticate(flow, credentials).await?;
+            principal.expires_at = self.expires_at;
+
+            Ok(principal)
+        }
+
+        fn challenge(&self) -> Option<Challenge> {
+            self.inner.challenge()
+        }
+    }
+
+    fn counting(expires_at: Option<SystemTime>) -> (Counting, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let auth = Counting {
+            inner: HTTPBasic::new("username", "password"),
+            calls: Arc::clone(&calls),
+            expires_at,
+        };
+
+        (auth, calls)
+    }
+
+    #[tokio::test]
+    async fn authenticate() -> Result<()> {
+        let (inner, calls) = counting(None);
+        let auth = Cached::new(inner);
+        let valid = Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ="); // username:password
+        let invalid = Credentials::new("Basic", "cGFzc3dvcmQ6dXNlcm5hbWU="); // password:username
+
+        for _ in 0..3 {
+            assert_eq!(
+                auth.authenticate(&flow(), Some(&valid)).await?.id,
+                "username"
+            );
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Cache is per client address
+        let other = Proxy::default().flow("10.0.0.1:1234".parse()?);
+        auth.authenticate(&other, Some(&valid)).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Failures are not cached
+        for _ in 0..2 {
+            assert!(matches!(
+                auth.authenticate(&flow(), Some(&invalid)).await,
+                Err(Error::NotAuthenticated)
+            ));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(auth.challenges(), vec![Challenge::new("Basic")]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authenticate_expiry() -> Result<()> {
+        let valid = Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ="); // username:password
+
+        let (inner, calls) = counting(None);
+        let auth = Cached::new(inner).ttl(Duration::ZERO);
+        auth.authenticate(&flow(), Some(&valid)).await?;
+        auth.authenticate(&flow(), Some(&valid)).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Identity expiring sooner than TTL caps cache lifetime
+        let (inner, calls) = counting(Some(SystemTime::now()));
+        let auth = Cached::new(inner);
+        auth.authenticate(&flow(), Some(&valid)).await?;
+        auth.authenticate(&flow(), Some(&valid)).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ttl_map() {
+        let mut map = TtlMap::new(2);
+        assert!(map.insert("a".to_string(), 1, Duration::ZERO));
+        assert!(map.insert("b".to_string(), 2, Duration::from_secs(60)));
+        assert_eq!(map.get("a"), None);
+        assert_eq!(map.get("b"), Some(2));
+
+        // Expired entries make room, live ones are kept
+        assert!(map.insert("c".to_string(), 3, Duration::from_secs(60)));
+        assert!(!map.insert("d".to_string(), 4, Duration::from_secs(60)));
+        assert_eq!(map.entries.len(), 2);
+        assert_eq!(map.get("b"), Some(2));
+        assert_eq!(map.get("d"), None);
+
+        // Existing keys are updated even when full
+        assert!(map.insert("b".to_string(), 5, Duration::from_secs(60)));
+        assert_eq!(map.get("b"), Some(5));
+    }
+}
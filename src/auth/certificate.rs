@@ -0,0 +1,236 @@
+//! Client certificate authenticator, for clients connected over mutual TLS.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tracing::{debug, trace};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use super::{Authenticator, Credentials, Error, Principal};
+use crate::proxy::Flow;
+
+/// Certificate subject field identifying client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubjectField {
+    /// Subject common name (CN).
+    CommonName,
+
+    /// DNS name in subject alternative names.
+    Dns,
+
+    /// Email address (RFC 822 name) in subject alternative names.
+    Email,
+
+    /// URI in subject alternative names, such as SPIFFE ID.
+    Uri,
+}
+
+/// Authenticator identifying clients by certificate presented during TLS handshake, ignoring credentials. Certificate
+/// must have been verified by proxy listener already, see [`TlsConfig`](crate::proxy::TlsConfig).
+///
+/// Subject organizational units (OU) become groups of authenticated user.
+#[derive(Debug)]
+pub struct ClientCertificate {
+    /// Fields to take identity from, first one present wins.
+    identities: Vec<SubjectField>,
+}
+
+impl Default for ClientCertificate {
+    fn default() -> Self {
+        Self {
+            identities: vec![
+                SubjectField::Uri,
+                SubjectField::Dns,
+                SubjectField::Email,
+                SubjectField::CommonName,
+            ],
+        }
+    }
+}
+
+impl ClientCertificate {
+    /// Create new authenticator taking identity from first of URI, DNS or email SAN, or subject CN.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take identity from given field only.
+    pub fn identity(mut self, identity: SubjectField) -> Self {
+        self.identities = vec![identity];
+        self
+    }
+
+    /// Values of field in certificate, in order.
+    fn values(cert: &X509Certificate, identity: SubjectField) -> Vec<String> {
+        if identity == SubjectField::CommonName {
+            return cert
+                .subject()
+                .iter_common_name()
+                .filter_map(|cn| cn.as_str().ok())
+                .map(str::to_string)
+                .collect();
+        }
+
+        let names = match cert.subject_alternative_name() {
+            Ok(Some(san)) => &san.value.general_names,
+            _ => return vec![],
+        };
+        names
+            .iter()
+            .filter_map(|name| match (identity, name) {
+                (SubjectField::Dns, GeneralName::DNSName(value))
+                | (SubjectField::Email, GeneralName::RFC822Name(value))
+                | (SubjectField::Uri, GeneralName::URI(value)) => Some(value.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn principal(&self, cert: &X509Certificate) -> Option<Principal> {
+        let id = self
+            .identities
+            .iter()
+            .find_map(|identity| Self::values(cert, *identity).into_iter().next())?;
+
+        let mut builder = Principal::builder();
+        builder.id(id);
+        if let Some(cn) = Self::values(cert, SubjectField::CommonName)
+            .into_iter()
+            .next()
+        {
+            builder.name(cn);
+        }
+        for ou in cert.subject().iter_organizational_unit() {
+            if let Ok(ou) = ou.as_str() {
+                builder.group(ou);
+            }
+        }
+        if let Ok(exp) = u64::try_from(cert.validity().not_after.timestamp()) {
+            builder.expires_at(UNIX_EPOCH + Duration::from_secs(exp));
+        }
+        builder
+            .claim("subject", cert.subject().to_string())
+            .claim("issuer", cert.issuer().to_string())
+            .claim("serial", cert.raw_serial_as_string());
+        for (key, identity) in [
+            ("dns_names", SubjectField::Dns),
+            ("emails", SubjectField::Email),
+            ("uris", SubjectField::Uri),
+        ] {
+            let values = Self::values(cert, identity);
+            if !values.is_empty() {
+                builder.claim(key, Value::from(values));
+            }
+        }
+
+        builder.build().ok()
+    }
+}
+
+#[async_trait]
+impl Authenticator for ClientCertificate {
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        _credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let der = flow
+            .tls()
+            .as_ref()
+            .and_then(|tls| tls.peer_certificates.first())
+            .ok_or_else(|| {
+                trace!("client presented no certificate");
+                Error::MissingCredentials
+            })?;
+
+        let (_, cert) = X509Certificate::from_der(der).map_err(|err| {
+            debug!("failed to parse client certificate: {err}");
+            Error::InvalidFormat { n: 0 }
+        })?;
+
+        self.principal(&cert).ok_or_else(|| {
+            debug!(
+                "client certificate {subject} has no identity field",
+                subject = cert.subject()
+            );
+            Error::NotAuthenticated
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use rcgen::SanType;
+
+    use super::{ClientCertificate, SubjectField};
+    use crate::{auth::{tests::flow, Authenticator, Error},
+                proxy::{tls::tests::{ca, issue},
+                        Flow, TlsInfo}};
+
+    fn flow_with(cert: &str) -> Flow {
+        let mut flow = flow();
+        *flow.tls_mut() = Some(TlsInfo {
+            peer_certificates: rustls_pemfile::certs(&mut cert.as_bytes()).unwrap(),
+            ..Default::default()
+        });
+
+        flow
+    }
+
+    #[tokio::test]
+    async fn authenticate() -> Result<()> {
+        let ca = ca();
+        let (cert, _) = issue(
+            &ca,
+            "Billing Service",
+            Some("payments"),
+            vec![
+                SanType::DnsName("billing.internal".to_string()),
+                SanType::URI("spiffe://example.com/billing".to_string()),
+            ],
+        );
+        let flow = flow_with(&cert);
+
+        let principal = ClientCertificate::new().authenticate(&flow, None).await?;
+        assert_eq!(principal.id, "spiffe://example.com/billing");
+        assert_eq!(principal.name.as_deref(), Some("Billing Service"));
+        assert_eq!(principal.groups, vec!["payments"]);
+        assert!(principal.expires_at.is_some());
+        assert_eq!(
+            principal.claims["dns_names"],
+            serde_json::json!(["billing.internal"])
+        );
+
+        let principal = ClientCertificate::new()
+            .identity(SubjectField::Dns)
+            .authenticate(&flow, None)
+            .await?;
+        assert_eq!(principal.id, "billing.internal");
+
+        let principal = ClientCertificate::new()
+            .identity(SubjectField::CommonName)
+            .authenticate(&flow, None)
+            .await?;
+        assert_eq!(principal.id, "Billing Service");
+
+        assert!(matches!(
+            ClientCertificate::new()
+                .identity(SubjectField::Email)
+                .authenticate(&flow, None)
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authenticate_without_certificate() {
+        assert!(matches!(
+            ClientCertificate::new().authenticate(&flow(), None).await,
+            Err(Error::MissingCredentials)
+        ));
+    }
+}
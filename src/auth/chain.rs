@@ -0,0 +1,282 @@
+//! Combinators composing multiple authenticators into one.
+
+use async_trait::async_trait;
+use tracing::trace;
+
+use super::{Authenticator, Challenge, Credentials, Error, Principal};
+use crate::proxy::Flow;
+
+type Auths = Vec<Box<dyn Authenticator + Send + Sync>>;
+
+/// Challenges of all inner authenticators, in order.
+fn challenges(auths: &Auths) -> Vec<Challenge> {
+    auths.iter().flat_map(|ab| ab.challenges()).collect()
+}
+
+/// Accepts identity of first inner authenticator that succeeds. Explicit denials are treated as ordinary failures,
+/// see [`FirstMatch`] to honor them.
+#[derive(Debug)]
+pub struct AnyOf {
+    auths: Auths,
+}
+
+impl AnyOf {
+    pub fn new(auths: Auths) -> Self {
+        Self { auths }
+    }
+}
+
+#[async_trait]
+impl Authenticator for AnyOf {
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let mut last = Error::NotAuthenticated;
+        for ab in self.auths.iter() {
+            match ab.authenticate(flow, credentials).await {
+                Ok(principal) => return Ok(principal),
+                Err(err @ Error::Denied(_)) => {
+                    trace!("authenticator {ab:?} failed: {err}");
+                    last = Error::NotAuthenticated;
+                }
+                Err(err) => {
+                    trace!("authenticator {ab:?} failed: {err}");
+                    last = err;
+                }
+            }
+        }
+
+        Err(last)
+    }
+
+    fn challenges(&self) -> Vec<Challenge> {
+        challenges(&self.auths)
+    }
+}
+
+/// Requires every inner authenticator to succeed, such as client address and password as two factors. Resulting
+/// identity is the one of first authenticator, with groups and claims of others merged in and earliest expiry.
+#[derive(Debug)]
+pub struct AllOf {
+    auths: Auths,
+}
+
+impl AllOf {
+    pub fn new(auths: Auths) -> Self {
+        Self { auths }
+    }
+}
+
+#[async_trait]
+impl Authenticator for AllOf {
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let mut merged: Option<Principal> = None;
+        for ab in self.auths.iter() {
+            let principal = ab.authenticate(flow, credentials).await?;
+            match merged.as_mut() {
+                None => merged = Some(principal),
+                Some(merged) => {
+                    for group in principal.groups {
+                        if !merged.in_group(&group) {
+                            merged.groups.push(group);
+                        }
+                    }
+                    for (key, value) in principal.claims {
+                        merged.claims.entry(key).or_insert(value);
+                    }
+                    merged.expires_at = match (merged.expires_at, principal.expires_at) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+            }
+        }
+
+        merged.ok_or(Error::NotAuthenticated)
+    }
+
+    fn challenges(&self) -> Vec<Challenge> {
+        challenges(&self.auths)
+    }
+}
+
+/// Accepts identity of first inner authenticator that succeeds, but stops at first one explicitly denying client.
+#[derive(Debug)]
+pub struct FirstMatch {
+    auths: Auths,
+}
+
+impl FirstMatch {
+    pub fn new(auths: Auths) -> Self {
+        Self { auths }
+    }
+}
+
+#[async_trait]
+impl Authenticator for FirstMatch {
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let mut last = Error::NotAuthenticated;
+        for ab in self.auths.iter() {
+            match ab.authenticate(flow, credentials).await {
+                Ok(principal) => return Ok(principal),
+                Err(err @ Error::Denied(_)) => return Err(err),
+                Err(err) => {
+                    trace!("authenticator {ab:?} failed: {err}");
+                    last = err;
+                }
+            }
+        }
+
+        Err(last)
+    }
+
+    fn challenges(&self) -> Vec<Challenge> {
+        challenges(&self.auths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+
+    use super::{AllOf, AnyOf, FirstMatch};
+    use crate::{auth::{tests::flow, Authenticator, Challenge, CidrAllowlist, Credentials, Error,
+                       HTTPBasic, Principal},
+                proxy::Flow};
+
+    /// Authenticator denying everyone.
+    #[derive(Debug)]
+    struct Deny;
+
+    #[async_trait]
+    impl Authenticator for Deny {
+        async fn authenticate(
+            &self,
+            _flow: &Flow,
+            _credentials: Option<&Credentials>,
+        ) -> Result<Principal, Error> {
+            Err(Error::Denied("blocked".to_string()))
+        }
+    }
+
+    /// Authenticator accepting everyone with fixed identity.
+    #[derive(Debug)]
+    struct Fixed(Principal);
+
+    #[async_trait]
+    impl Authenticator for Fixed {
+        async fn authenticate(
+            &self,
+            _flow: &Flow,
+            _credentials: Option<&Credentials>,
+        ) -> Result<Principal, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn basic() -> Credentials {
+        Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ=") // username:password
+    }
+
+    #[tokio::test]
+    async fn any_of() -> Result<()> {
+        let auth = AnyOf::new(vec![
+            Box::new(Deny),
+            Box::new(HTTPBasic::new("username", "password")),
+        ]);
+
+        let principal = auth.authenticate(&flow(), Some(&basic())).await?;
+        assert_eq!(principal.id, "username");
+        assert!(matches!(
+            auth.authenticate(&flow(), None).await,
+            Err(Error::MissingCredentials)
+        ));
+        assert_eq!(auth.challenges(), vec![Challenge::new("Basic")]);
+
+        // Denial of last authenticator is ordinary failure too
+        let auth = AnyOf::new(vec![
+            Box::new(HTTPBasic::new("username", "password")),
+            Box::new(Deny),
+        ]);
+        assert!(matches!(
+            auth.authenticate(&flow(), None).await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_of() -> Result<()> {
+        let now = SystemTime::now();
+        let mut network = Principal::new("office");
+        network.groups = vec!["staff".to_string()];
+        network.expires_at = Some(now);
+        let mut user = Principal::new("username");
+        user.groups = vec!["admin".to_string(), "staff".to_string()];
+        user.expires_at = Some(now + Duration::from_secs(60));
+
+        let auth = AllOf::new(vec![
+            Box::new(HTTPBasic::new("username", "password")),
+            Box::new(Fixed(network)),
+            Box::new(Fixed(user)),
+        ]);
+        let principal = auth.authenticate(&flow(), Some(&basic())).await?;
+        assert_eq!(principal.id, "username");
+        assert_eq!(principal.groups, vec!["staff", "admin"]);
+        assert_eq!(principal.expires_at, Some(now));
+
+        // Every factor is required
+        let auth = AllOf::new(vec![
+            Box::new(CidrAllowlist::new().allow("127.0.0.0/8".parse()?, Principal::new("local"))),
+            Box::new(HTTPBasic::new("username", "password")),
+        ]);
+        assert_eq!(
+            auth.authenticate(&flow(), Some(&basic())).await?.id,
+            "local"
+        );
+        assert!(matches!(
+            auth.authenticate(&flow(), None).await,
+            Err(Error::MissingCredentials)
+        ));
+        assert!(matches!(
+            AllOf::new(vec![]).authenticate(&flow(), None).await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn first_match() -> Result<()> {
+        let auth = FirstMatch::new(vec![
+            Box::new(HTTPBasic::new("username", "password")),
+            Box::new(Deny),
+            Box::new(Fixed(Principal::new("anonymous"))),
+        ]);
+
+        let principal = auth.authenticate(&flow(), Some(&basic())).await?;
+        assert_eq!(principal.id, "username");
+
+        // Denial short-circuits remaining authenticators
+        assert!(matches!(
+            auth.authenticate(&flow(), None).await,
+            Err(Error::Denied(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::{self, Display};
+
+/// Authentication challenge sent in `Proxy-Authenticate` header.
+///
+/// https://www.rfc-editor.org/rfc/rfc9110#section-11.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: String,
+
+    /// Protection space; proxy fills in its own realm if not set.
+    pub realm: Option<String>,
+
+    /// Additional auth-params, in order.
+    pub params: Vec<Param>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub value: String,
+
+    /// Whether value is sent as quoted-string rather than token.
+    pub quoted: bool,
+}
+
+impl Challenge {
+    pub fn new<S>(scheme: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            scheme: scheme.as_ref().to_string(),
+            realm: None,
+            params: vec![],
+        }
+    }
+
+    pub fn realm<S>(mut self, realm: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.realm = Some(realm.as_ref().to_string());
+        self
+    }
+
+    /// Add parameter with quoted-string value.
+    pub fn param<S>(mut self, name: S, value: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.params.push(Param {
+            name: name.as_ref().to_string(),
+            value: value.as_ref().to_string(),
+            quoted: true,
+        });
+        self
+    }
+
+    /// Add parameter with token value, such as `algorithm=SHA-256`.
+    pub fn token<S>(mut self, name: S, value: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.params.push(Param {
+            name: name.as_ref().to_string(),
+            value: value.as_ref().to_string(),
+            quoted: false,
+        });
+        self
+    }
+}
+
+impl Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.scheme)?;
+
+        let realm = self.realm.as_ref().map(|realm| Param {
+            name: "realm".to_string(),
+            value: realm.clone(),
+            quoted: true,
+        });
+        for (i, param) in realm.iter().chain(self.params.iter()).enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            if param.quoted {
+                let escaped = param.value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "{sep}{name}=\"{escaped}\"", name = param.name)?;
+            } else {
+                write!(
+                    f,
+                    "{sep}{name}={value}",
+                    name = param.name,
+                    value = param.value
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Challenge;
+
+    #[test]
+    fn display() {
+        assert_eq!(Challenge::new("Bearer").to_string(), "Bearer");
+        assert_eq!(
+            Challenge::new("Digest")
+                .realm(r#"kkowa "proxy""#)
+                .param("qop", "auth")
+                .token("algorithm", "SHA-256")
+                .to_string(),
+            r#"Digest realm="kkowa \"proxy\"", qop="auth", algorithm=SHA-256"#
+        );
+    }
+}
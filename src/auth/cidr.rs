@@ -0,0 +1,215 @@
+//! Client address based authenticator.
+
+use std::{fmt::{self, Display},
+          net::IpAddr,
+          str::FromStr};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::trace;
+
+use super::{Authenticator, Credentials, Principal};
+use crate::proxy::Flow;
+
+#[derive(Debug, Error)]
+pub enum ParseNetworkError {
+    #[error("invalid address {0}")]
+    Address(String),
+
+    #[error("invalid prefix length {0}")]
+    Prefix(String),
+}
+
+/// IP network in CIDR notation, such as `10.0.0.0/8` or `fd00::/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Create new network, rejecting prefix longer than address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, ParseNetworkError> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(ParseNetworkError::Prefix(prefix.to_string()));
+        }
+
+        Ok(Self { addr, prefix })
+    }
+
+    /// Whether address belongs to network. IPv4-mapped IPv6 addresses match IPv4 networks.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            addr => addr,
+        };
+
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = ParseNetworkError;
+
+    /// Parse network from CIDR notation. Bare address is treated as single host network.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| ParseNetworkError::Address(addr.to_string()))?;
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .map_err(|_| ParseNetworkError::Prefix(prefix.to_string()))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+
+        Self::new(addr, prefix)
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Authenticator identifying clients by their source address, regardless of credentials. Networks are matched in
+/// order they were added, first match wins.
+#[derive(Debug, Default)]
+pub struct CidrAllowlist {
+    /// Networks with identity of their clients, `None` for denied ones.
+    networks: Vec<(Network, Option<Principal>)>,
+}
+
+impl CidrAllowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticate clients from network as given user.
+    pub fn allow(mut self, network: Network, principal: Principal) -> Self {
+        self.networks.push((network, Some(principal)));
+        self
+    }
+
+    /// Explicitly deny clients from network, so that no other authenticator is tried.
+    pub fn deny(mut self, network: Network) -> Self {
+        self.networks.push((network, None));
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for CidrAllowlist {
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        _credentials: Option<&Credentials>,
+    ) -> Result<Principal, super::Error> {
+        let ip = flow.client().ip();
+        match self
+            .networks
+            .iter()
+            .find(|(network, _)| network.contains(ip))
+        {
+            Some((network, Some(principal))) => {
+                trace!("client {ip} matched network {network}");
+                Ok(principal.clone())
+            }
+            Some((network, None)) => Err(super::Error::Denied(format!(
+                "client {ip} is in denied network {network}"
+            ))),
+            None => Err(super::Error::NotAuthenticated),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{CidrAllowlist, Network};
+    use crate::{auth::{tests::flow, Authenticator, Credentials, Error, Principal},
+                proxy::Proxy};
+
+    #[test]
+    fn network() -> Result<()> {
+        let net: Network = "10.1.0.0/16".parse()?;
+        assert!(net.contains("10.1.2.3".parse()?));
+        assert!(!net.contains("10.2.0.1".parse()?));
+        assert!(net.contains("::ffff:10.1.0.1".parse()?));
+        assert!(!net.contains("fd00::1".parse()?));
+
+        let host: Network = "192.168.0.1".parse()?;
+        assert_eq!(host.to_string(), "192.168.0.1/32");
+        assert!(host.contains("192.168.0.1".parse()?));
+        assert!(!host.contains("192.168.0.2".parse()?));
+
+        let any: Network = "0.0.0.0/0".parse()?;
+        assert!(any.contains("8.8.8.8".parse()?));
+
+        let v6: Network = "fd00::/8".parse()?;
+        assert!(v6.contains("fd12::1".parse()?));
+        assert!(!v6.contains("fe80::1".parse()?));
+
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("10.0.0/8".parse::<Network>().is_err());
+        assert!("fd00::/abc".parse::<Network>().is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authenticate() -> Result<()> {
+        let auth = CidrAllowlist::new()
+            .deny("127.0.0.2".parse()?)
+            .allow("127.0.0.0/8".parse()?, Principal::new("loopback"))
+            .allow("0.0.0.0/0".parse()?, Principal::new("anyone"));
+
+        // Credentials are ignored
+        let principal = auth.authenticate(&flow(), None).await?;
+        assert_eq!(principal.id, "loopback");
+        let principal = auth
+            .authenticate(&flow(), Some(&Credentials::new("Basic", "invalid")))
+            .await?;
+        assert_eq!(principal.id, "loopback");
+
+        let remote = Proxy::default().flow("10.0.0.1:1234".parse()?);
+        let principal = auth.authenticate(&remote, None).await?;
+        assert_eq!(principal.id, "anyone");
+
+        let denied = Proxy::default().flow("127.0.0.2:1234".parse()?);
+        assert!(matches!(
+            auth.authenticate(&denied, None).await,
+            Err(Error::Denied(_))
+        ));
+
+        let auth = CidrAllowlist::new().allow("10.0.0.0/8".parse()?, Principal::new("internal"));
+        assert!(matches!(
+            auth.authenticate(&flow(), None).await,
+            Err(Error::NotAuthenticated)
+        ));
+        assert!(auth.challenge().is_none());
+
+        Ok(())
+    }
+}
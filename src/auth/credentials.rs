@@ -1,10 +1,12 @@
-use std::fmt::Debug;
+use std::{collections::HashMap,
+          fmt::{self, Debug, Display}};
 
 use getset::Getters;
 use thiserror::Error;
 use tracing::{debug, trace};
+use zeroize::Zeroizing;
 
-use crate::http::{header, Request};
+use crate::http::{header, Method, Request, Uri};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -14,18 +16,29 @@ pub enum Error {
     #[error("failed to parse provided data into desired format")]
     InvalidFormat { n: usize },
 
+    #[error("unexpected scheme {0}")]
+    UnexpectedScheme(String),
+
     #[error("unknown error")]
     Unknown,
 }
 
-// TODO: Implement its own Debug / Fmt traits to mask credentials for security
-#[derive(Clone, Debug, PartialEq, Eq, Getters)]
+/// Proxy authentication credentials. Secret part is wiped from memory on drop and never printed, so credentials are
+/// safe to be debug-printed as part of flow.
+#[derive(Clone, PartialEq, Eq, Getters)]
 pub struct Credentials {
     #[getset(get = "pub")]
     scheme: String,
 
+    credentials: Zeroizing<String>,
+
+    /// Method of request credentials were sent with, as schemes like Digest sign it.
     #[getset(get = "pub")]
-    credentials: String,
+    method: Option<Method>,
+
+    /// Target URI of request credentials were sent with.
+    #[getset(get = "pub")]
+    uri: Option<Uri>,
 }
 
 impl Credentials {
@@ -35,35 +48,150 @@ pub fn new<S>(scheme: S, credentials: S) -> Self
     {
         Self {
             scheme: scheme.as_ref().to_string(),
-            credentials: credentials.as_ref().to_string(),
+            credentials: Zeroizing::new(credentials.as_ref().to_string()),
+            method: None,
+            uri: None,
+        }
+    }
+
+    /// Bind credentials to request they were sent with.
+    pub fn with_request(mut self, method: Method, uri: Uri) -> Self {
+        self.method = Some(method);
+        self.uri = Some(uri);
+        self
+    }
+
+    /// Raw credentials data following scheme, such as token or auth-param list.
+    pub fn credentials(&self) -> &str {
+        &self.credentials
+    }
+
+    /// Whether credentials are of given scheme, compared case-insensitively.
+    pub fn is_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+
+    /// Decode HTTP basic credentials, base64 encoded `<username>:<password>`, into username and password.
+    pub fn basic(&self) -> Result<(String, Zeroizing<String>), Error> {
+        if !self.is_scheme("basic") {
+            return Err(Error::UnexpectedScheme(self.scheme.clone()));
+        }
+
+        let decoded = Zeroizing::new(
+            base64::decode(self.credentials.as_bytes())
+                .map_err(|_| Error::InvalidFormat { n: 0 })?,
+        );
+        let decoded = std::str::from_utf8(&decoded).map_err(|_| Error::InvalidFormat { n: 0 })?;
+        // Password may contain colons, only username may not
+        // https://www.rfc-editor.org/rfc/rfc7617#section-2
+        match decoded.split_once(':') {
+            Some((username, password)) => {
+                Ok((username.to_string(), Zeroizing::new(password.to_string())))
+            }
+            None => {
+                debug!("credentials data has no colon separating username and password");
+                Err(Error::InvalidFormat { n: 1 })
+            }
         }
     }
+
+    /// Bearer token, if credentials are of bearer scheme.
+    pub fn bearer(&self) -> Option<&str> {
+        self.is_scheme("bearer")
+            .then_some(self.credentials.as_str())
+    }
+
+    /// Parse credentials as comma-separated auth-param list, such as `username="jdoe", qop=auth`. Parameter names
+    /// are lowercased and quoted values unescaped.
+    pub fn params(&self) -> HashMap<String, String> {
+        let mut params = HashMap::new();
+        let mut rest = self.credentials.as_str();
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+            let Some((name, value)) = rest.split_once('=') else {
+                break;
+            };
+            let value = value.trim_start();
+
+            let (value, remaining) = match value.strip_prefix('"') {
+                Some(quoted) => {
+                    let (mut unescaped, mut end) = (String::new(), quoted.len());
+                    let mut chars = quoted.char_indices();
+                    while let Some((i, c)) = chars.next() {
+                        match c {
+                            '\\' => unescaped.extend(chars.next().map(|(_, c)| c)),
+                            '"' => {
+                                end = i + 1;
+                                break;
+                            }
+                            c => unescaped.push(c),
+                        }
+                    }
+
+                    (unescaped, &quoted[end..])
+                }
+                None => {
+                    let end = value.find(',').unwrap_or(value.len());
+
+                    (value[..end].trim().to_string(), &value[end..])
+                }
+            };
+            params.insert(name.trim().to_lowercase(), value);
+            rest = remaining;
+        }
+
+        params
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("scheme", &self.scheme)
+            .field("credentials", &"<redacted>")
+            .field("method", &self.method)
+            .field("uri", &self.uri)
+            .finish()
+    }
+}
+
+impl Display for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <redacted>", self.scheme)
+    }
 }
 
 impl TryFrom<&Request> for Credentials {
     type Error = Error;
 
-    /// Extract credentials from proxy authorization header in request.
+    /// Extract credentials from proxy authorization header in request. Scheme and credentials may be separated by
+    /// any amount of whitespace, and surrounding whitespace is ignored.
     fn try_from(request: &Request) -> Result<Self, Self::Error> {
         match request.headers.get(header::PROXY_AUTHORIZATION) {
             Some(value) => {
-                // Split scheme and credentials fields
-                // NOTE: No base64 handling here (yet?)
-                let arr: [&str; 2] = value
+                // Split scheme and credentials fields; credentials are either single token (`Basic dXNlcm5hbWU6...`)
+                // or auth-param list (`Digest username="jdoe", ...`)
+                let value = value
                     .to_str()
-                    .expect("failed to convert header value to string")
-                    .split_whitespace()
-                    .collect::<Vec<&str>>()
-                    .try_into()
-                    .map_err(|v: Vec<&str>| Error::InvalidFormat { n: v.len() })?;
+                    .map_err(|_| Error::InvalidFormat { n: 0 })?
+                    .trim();
+                let Some((scheme, credentials)) = value.split_once(char::is_whitespace) else {
+                    return Err(Error::InvalidFormat { n: 1 });
+                };
+                let credentials = credentials.trim();
+                if credentials.contains(char::is_whitespace) && !credentials.contains('=') {
+                    return Err(Error::InvalidFormat {
+                        n: value.split_whitespace().count(),
+                    });
+                }
 
-                let (scheme, credentials) = (arr[0], arr[1]);
                 trace!(
                     "parsed credentials with scheme {scheme} and {n}-length credentials data",
                     n = credentials.len()
                 );
 
-                Ok(Credentials::new(scheme, credentials))
+                Ok(Credentials::new(scheme, credentials)
+                    .with_request(request.method.clone(), request.uri.clone()))
             }
             None => {
                 {
@@ -89,8 +217,8 @@ fn try_from(request: &Request) -> Result<Self, Self::Error> {
 mod tests {
     use anyhow::Result;
 
-    use super::Credentials;
-    use crate::http::{header, Request};
+    use super::{Credentials, Error};
+    use crate::http::{header, Method, Request, Uri};
 
     #[test]
     fn try_from() -> Result<()> {
@@ -105,11 +233,97 @@ fn try_from() -> Result<()> {
         assert_eq!(
             Credentials::try_from(&req)?,
             Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ=")
+                .with_request(Method::GET, Uri::default())
         );
 
         Ok(())
     }
 
+    #[test]
+    fn try_from_whitespace() -> Result<()> {
+        let req = Request::builder()
+            .header(
+                header::PROXY_AUTHORIZATION,
+                "  bAsIc \t  dXNlcm5hbWU6cGFzc3dvcmQ=  ".parse().unwrap(),
+            )
+            .build()
+            .unwrap();
+        let credentials = Credentials::try_from(&req)?;
+
+        assert!(credentials.is_scheme("Basic"));
+        assert_eq!(credentials.credentials(), "dXNlcm5hbWU6cGFzc3dvcmQ=");
+
+        Ok(())
+    }
+
+    #[test]
+    fn basic() -> Result<()> {
+        let (username, password) = Credentials::new("basic", "dXNlcm5hbWU6cGFzc3dvcmQ=").basic()?; // username:password
+        assert_eq!(username, "username");
+        assert_eq!(password.as_str(), "password");
+
+        for (decoded, encoded) in [
+            (("username", "pass:word"), "dXNlcm5hbWU6cGFzczp3b3Jk"),
+            (("username", "password:"), "dXNlcm5hbWU6cGFzc3dvcmQ6"),
+            (("username", ""), "dXNlcm5hbWU6"),
+        ] {
+            let (username, password) = Credentials::new("Basic", encoded).basic()?;
+            assert_eq!((username.as_str(), password.as_str()), decoded);
+        }
+
+        assert!(matches!(
+            Credentials::new("Basic", "dXNlcm5hbWU=").basic(), // username
+            Err(Error::InvalidFormat { n: 1 })
+        ));
+        assert!(matches!(
+            Credentials::new("Basic", "not base64!").basic(),
+            Err(Error::InvalidFormat { n: 0 })
+        ));
+        assert!(matches!(
+            Credentials::new("Bearer", "dXNlcm5hbWU6cGFzc3dvcmQ=").basic(),
+            Err(Error::UnexpectedScheme(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bearer() {
+        assert_eq!(Credentials::new("BEARER", "token").bearer(), Some("token"));
+        assert_eq!(Credentials::new("Basic", "token").bearer(), None);
+    }
+
+    #[test]
+    fn redacted() {
+        let credentials = Credentials::new("Bearer", "secret-token");
+
+        assert_eq!(credentials.to_string(), "Bearer <redacted>");
+        assert!(!format!("{credentials:?}").contains("secret-token"));
+        assert!(!format!("{:?}", Some(credentials)).contains("secret-token"));
+    }
+
+    #[test]
+    fn try_from_params() -> Result<()> {
+        let req = Request::builder()
+            .header(
+                header::PROXY_AUTHORIZATION,
+                r#"Digest username="jdoe", realm="kkowa, \"proxy\"", qop=auth,nc=00000001"#
+                    .parse()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let params = Credentials::try_from(&req)?.params();
+
+        assert_eq!(params["username"], "jdoe");
+        assert_eq!(params["realm"], r#"kkowa, "proxy""#);
+        assert_eq!(params["qop"], "auth");
+        assert_eq!(params["nc"], "00000001");
+        assert_eq!(params.len(), 4);
+
+        Ok(())
+    }
+
     #[test]
     fn try_from_header_not_set() -> Result<()> {
         let err = Credentials::try_from(&Request::default()).err().unwrap();
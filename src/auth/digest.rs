@@ -0,0 +1,484 @@
+//! HTTP Digest access authentication.
+//!
+//! https://www.rfc-editor.org/rfc/rfc7616
+
+use std::{collections::{BTreeSet, HashMap},
+          sync::Mutex,
+          time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use rand::RngCore;
+use sha2::{Digest as _, Sha256};
+use subtle::ConstantTimeEq;
+use tracing::{debug, trace};
+
+use super::{Authenticator, Challenge, Credentials, Error, Principal};
+use crate::{http::Method, proxy::Flow};
+
+/// Hash algorithm used to compute digests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    /// Name of algorithm as in `algorithm` parameter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Sha256 => "SHA-256",
+        }
+    }
+
+    /// Hex encoded hash of data.
+    pub fn hash(&self, data: &str) -> String {
+        match self {
+            Self::Md5 => format!("{:x}", Md5::digest(data)),
+            Self::Sha256 => format!("{:x}", Sha256::digest(data)),
+        }
+    }
+}
+
+/// Maximum number of nonces in use tracked at once; oldest ones are forgotten beyond this.
+const MAX_NONCES: usize = 10_000;
+
+/// Nonces in use.
+#[derive(Debug, Default)]
+struct Nonces {
+    /// Last nonce count seen for each nonce, to reject replayed requests.
+    counts: HashMap<String, u32>,
+
+    /// Tracked nonces by issue time, oldest first.
+    order: BTreeSet<(SystemTime, String)>,
+
+    /// Latest issue time of nonces forgotten before they expired. Untracked nonces issued until then may have been
+    /// used already, so they are treated as stale.
+    watermark: Option<SystemTime>,
+}
+
+/// Digest authenticator with `qop=auth`. Nonces are issued with each challenge, expire after configured TTL and
+/// accept each nonce count only once.
+///
+/// Nonces carry their issue time, signed with secret of authenticator, so that issuing them takes no state; only
+/// nonce counts of nonces actually used are tracked.
+#[derive(Debug)]
+pub struct Digest {
+    realm: String,
+    algorithm: Algorithm,
+    nonce_ttl: Duration,
+
+    /// Passwords by username.
+    users: HashMap<String, String>,
+
+    /// Random key signing nonces.
+    secret: [u8; 32],
+    nonces: Mutex<Nonces>,
+}
+
+impl Digest {
+    /// Create new authenticator for given realm, using SHA-256 and nonces valid for 5 minutes.
+    pub fn new<S>(realm: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        Self {
+            realm: realm.as_ref().to_string(),
+            algorithm: Algorithm::Sha256,
+            nonce_ttl: Duration::from_secs(300),
+            users: HashMap::new(),
+            secret,
+            nonces: Mutex::default(),
+        }
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn nonce_ttl(mut self, ttl: Duration) -> Self {
+        self.nonce_ttl = ttl;
+        self
+    }
+
+    /// Add user with password.
+    pub fn user<S>(mut self, username: S, password: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.users
+            .insert(username.as_ref().to_string(), password.as_ref().to_string());
+        self
+    }
+
+    /// Signature of nonce data.
+    fn sign(&self, data: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(data.as_bytes());
+
+        mac.finalize().into_bytes()[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Issue new nonce; issue time in nanoseconds and random value, followed by their signature. Fine-grained issue
+    /// time keeps eviction watermark from catching nonces issued right after evicted one.
+    fn issue(&self) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let data = format!(
+            "{timestamp:016x}{random:016x}",
+            random = rand::thread_rng().next_u64()
+        );
+
+        format!("{data}{signature}", signature = self.sign(&data))
+    }
+
+    /// Issue time of nonce, if nonce was issued by this authenticator.
+    fn issued_at(&self, nonce: &str) -> Option<SystemTime> {
+        let (data, signature) = (nonce.get(..32)?, nonce.get(32..)?);
+        if !bool::from(self.sign(data).as_bytes().ct_eq(signature.as_bytes())) {
+            return None;
+        }
+        let timestamp = u64::from_str_radix(&data[..16], 16).ok()?;
+
+        Some(UNIX_EPOCH + Duration::from_nanos(timestamp))
+    }
+
+    fn is_expired(&self, issued_at: SystemTime) -> bool {
+        issued_at
+            .elapsed()
+            .map_or(false, |elapsed| elapsed >= self.nonce_ttl)
+    }
+
+    /// Record use of nonce with given count, failing if count is replayed or nonce might have been forgotten.
+    fn consume(&self, nonce: &str, issued_at: SystemTime, count: u32) -> Result<(), Error> {
+        let mut nonces = self.nonces.lock().unwrap();
+        if let Some(last) = nonces.counts.get_mut(nonce) {
+            if count <= *last {
+                debug!("nonce count {count} of {nonce} has been used already");
+                return Err(Error::NotAuthenticated);
+            }
+            *last = count;
+
+            return Ok(());
+        }
+        if nonces
+            .watermark
+            .map_or(false, |watermark| issued_at <= watermark)
+        {
+            debug!("nonce {nonce} is not tracked anymore");
+            return Err(Error::StaleNonce);
+        }
+
+        // Expired nonces are rejected before lookup, so forgetting them is safe
+        while let Some((oldest, _)) = nonces.order.first() {
+            if !self.is_expired(*oldest) && nonces.order.len() < MAX_NONCES {
+                break;
+            }
+            let (oldest, forgotten) = nonces.order.pop_first().unwrap();
+            nonces.counts.remove(&forgotten);
+            if !self.is_expired(oldest) {
+                debug!("too many nonces in use, forgetting {forgotten}");
+                nonces.watermark = nonces.watermark.max(Some(oldest));
+            }
+        }
+        nonces.counts.insert(nonce.to_string(), count);
+        nonces.order.insert((issued_at, nonce.to_string()));
+
+        Ok(())
+    }
+
+    /// Expected `response` parameter for request.
+    #[allow(clippy::too_many_arguments)]
+    fn response(
+        &self,
+        username: &str,
+        password: &str,
+        method: &Method,
+        uri: &str,
+        nonce: &str,
+        nc: &str,
+        cnonce: &str,
+    ) -> String {
+        let h = |data: String| self.algorithm.hash(&data);
+        let ha1 = h(format!("{username}:{realm}:{password}", realm = self.realm));
+        let ha2 = h(format!("{method}:{uri}"));
+
+        h(format!("{ha1}:{nonce}:{nc}:{cnonce}:auth:{ha2}"))
+    }
+}
+
+#[async_trait]
+impl Authenticator for Digest {
+    async fn authenticate(
+        &self,
+        _flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let credentials = credentials.ok_or(Error::MissingCredentials)?;
+        if !credentials.is_scheme("digest") {
+            trace!(
+                "scheme expected \"digest\" but got \"{got}\"",
+                got = credentials.scheme()
+            );
+            return Err(Error::InvalidScheme {
+                got: credentials.scheme().to_string(),
+                expect: "digest".to_string(),
+            });
+        }
+
+        let params = credentials.params();
+        let param = |name: &str| params.get(name).map(String::as_str);
+        let (
+            Some(username),
+            Some(realm),
+            Some(nonce),
+            Some(uri),
+            Some(response),
+            Some(qop),
+            Some(nc),
+            Some(cnonce),
+        ) = (
+            param("username"),
+            param("realm"),
+            param("nonce"),
+            param("uri"),
+            param("response"),
+            param("qop"),
+            param("nc"),
+            param("cnonce"),
+        ) else {
+            debug!("digest credentials lack required parameters");
+            return Err(Error::InvalidFormat { n: params.len() });
+        };
+
+        // Algorithm defaults to MD5 if not given
+        // https://www.rfc-editor.org/rfc/rfc7616#section-3.4
+        let algorithm = param("algorithm").unwrap_or("MD5");
+        if realm != self.realm || qop != "auth" || algorithm != self.algorithm.name() {
+            debug!("unsupported digest parameters realm={realm}, qop={qop}, algorithm={algorithm}");
+            return Err(Error::NotAuthenticated);
+        }
+
+        // Digest must be computed for request it is sent with
+        let (Some(method), Some(target)) = (credentials.method(), credentials.uri()) else {
+            return Err(Error::NotAuthenticated);
+        };
+        let target_matches = *target == uri
+            || target
+                .path_and_query()
+                .map_or(false, |pq| uri == pq.as_str())
+            || (*method == Method::CONNECT
+                && target.authority().map_or(false, |a| uri == a.as_str()));
+        if !target_matches {
+            debug!("digest URI {uri} does not match request target {target}");
+            return Err(Error::NotAuthenticated);
+        }
+
+        let Some(issued_at) = self.issued_at(nonce) else {
+            debug!("unknown nonce {nonce}");
+            return Err(Error::NotAuthenticated);
+        };
+        let Some(password) = self.users.get(username) else {
+            debug!("unknown user {username}");
+            return Err(Error::NotAuthenticated);
+        };
+        let expected = self.response(username, password, method, uri, nonce, nc, cnonce);
+        if !bool::from(expected.as_bytes().ct_eq(response.as_bytes())) {
+            return Err(Error::NotAuthenticated);
+        }
+
+        // Client knows password, it only needs to retry with fresh nonce
+        if self.is_expired(issued_at) {
+            debug!("nonce {nonce} has expired");
+            return Err(Error::StaleNonce);
+        }
+
+        let count = u32::from_str_radix(nc, 16).map_err(|_| Error::InvalidFormat { n: 1 })?;
+        self.consume(nonce, issued_at, count)?;
+
+        Ok(Principal::new(username))
+    }
+
+    fn challenge(&self) -> Option<Challenge> {
+        Some(
+            Challenge::new("Digest")
+                .realm(&self.realm)
+                .param("qop", "auth")
+                .token("algorithm", self.algorithm.name())
+                .param("nonce", self.issue().as_str()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Algorithm, Digest};
+    use crate::{auth::{tests::flow, Authenticator, Credentials, Error},
+                http::{Method, Uri}};
+
+    /// Extract nonce from challenge.
+    fn nonce(digest: &Digest) -> String {
+        let challenge = digest.challenge().unwrap();
+        let nonce = challenge.params.iter().find(|p| p.name == "nonce").unwrap();
+
+        nonce.value.clone()
+    }
+
+    fn credentials(digest: &Digest, nonce: &str, nc: &str, password: &str) -> Credentials {
+        let uri = "http://example.com/index.html";
+        let response = digest.response("jdoe", password, &Method::GET, uri, nonce, nc, "0a4f113b");
+        let value = format!(
+            r#"username="jdoe", realm="kkowa", nonce="{nonce}", uri="{uri}", algorithm={algorithm}, response="{response}", qop=auth, nc={nc}, cnonce="0a4f113b""#,
+            algorithm = digest.algorithm.name()
+        );
+
+        Credentials::new("Digest", value.as_str()).with_request(
+            Method::GET,
+            Uri::from_static("http://example.com/index.html"),
+        )
+    }
+
+    #[test]
+    fn algorithm() {
+        assert_eq!(Algorithm::Md5.hash(""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            Algorithm::Sha256.hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[tokio::test]
+    async fn authenticate() {
+        for algorithm in [Algorithm::Md5, Algorithm::Sha256] {
+            let digest = Digest::new("kkowa")
+                .algorithm(algorithm)
+                .user("jdoe", "password");
+            let nonce = nonce(&digest);
+
+            let principal = digest
+                .authenticate(
+                    &flow(),
+                    Some(&credentials(&digest, &nonce, "00000001", "password")),
+                )
+                .await
+                .unwrap();
+            assert_eq!(principal.id, "jdoe");
+
+            // Next request with same nonce must increase nonce count
+            assert!(digest
+                .authenticate(
+                    &flow(),
+                    Some(&credentials(&digest, &nonce, "00000002", "password"))
+                )
+                .await
+                .is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticate_replayed() {
+        let digest = Digest::new("kkowa").user("jdoe", "password");
+        let nonce = nonce(&digest);
+        let credentials = credentials(&digest, &nonce, "00000001", "password");
+
+        assert!(digest
+            .authenticate(&flow(), Some(&credentials))
+            .await
+            .is_ok());
+        assert!(matches!(
+            digest.authenticate(&flow(), Some(&credentials)).await,
+            Err(Error::NotAuthenticated)
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_unauthenticated() {
+        let digest = Digest::new("kkowa")
+            .nonce_ttl(Duration::ZERO)
+            .user("jdoe", "password");
+        let nonce = nonce(&digest);
+
+        // Wrong password
+        assert!(matches!(
+            digest
+                .authenticate(
+                    &flow(),
+                    Some(&credentials(&digest, &nonce, "00000001", "drowssap"))
+                )
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        // Expired nonce, with otherwise correct digest
+        assert!(matches!(
+            digest
+                .authenticate(
+                    &flow(),
+                    Some(&credentials(&digest, &nonce, "00000001", "password"))
+                )
+                .await,
+            Err(Error::StaleNonce)
+        ));
+
+        // Unknown nonce
+        assert!(matches!(
+            digest
+                .authenticate(
+                    &flow(),
+                    Some(&credentials(&digest, "deadbeef", "00000001", "password"))
+                )
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+    }
+
+    #[tokio::test]
+    async fn nonces_bounded() {
+        let digest = Digest::new("kkowa").user("jdoe", "password");
+
+        // Challenges take no state
+        for _ in 0..1_000 {
+            nonce(&digest);
+        }
+        assert!(digest.nonces.lock().unwrap().counts.is_empty());
+
+        // Tampered nonces are rejected
+        let mut tampered = nonce(&digest);
+        tampered.replace_range(..16, &format!("{:016x}", u64::MAX));
+        assert!(digest.issued_at(&tampered).is_none());
+
+        // Nonces in use are tracked up to limit
+        let first = nonce(&digest);
+        let first_issued_at = digest.issued_at(&first).unwrap();
+        assert!(digest.consume(&first, first_issued_at, 1).is_ok());
+        for _ in 0..super::MAX_NONCES + 10 {
+            let nonce = nonce(&digest);
+            let issued_at = digest.issued_at(&nonce).unwrap();
+            assert!(digest.consume(&nonce, issued_at, 1).is_ok());
+        }
+        let nonces = digest.nonces.lock().unwrap();
+        assert_eq!(nonces.counts.len(), super::MAX_NONCES);
+        assert_eq!(nonces.order.len(), super::MAX_NONCES);
+        drop(nonces);
+
+        // Replay of forgotten nonce is not taken as first use
+        assert!(matches!(
+            digest.consume(&first, first_issued_at, 1),
+            Err(Error::StaleNonce)
+        ));
+    }
+}
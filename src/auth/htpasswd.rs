@@ -0,0 +1,437 @@
+//! Authenticator backed by Apache htpasswd file.
+//!
+//! https://httpd.apache.org/docs/2.4/misc/password_encryptions.html
+
+use std::{collections::HashMap,
+          path::{Path, PathBuf},
+          sync::{Mutex, RwLock},
+          time::{Duration, Instant, SystemTime}};
+
+use async_trait::async_trait;
+use md5::{Digest, Md5};
+use subtle::ConstantTimeEq;
+use tracing::{debug, info, warn};
+
+use super::{basic, Authenticator, Challenge, Credentials, Error, Principal};
+use crate::proxy::Flow;
+
+/// Characters used by crypt(3) flavored base64 encoding.
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Hash verified for unknown users when file has no entries to borrow one from.
+const DUMMY_HASH: &str = "$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20";
+
+/// Password hash of a htpasswd entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Hash {
+    Bcrypt(String),
+    Sha256(String),
+    Sha512(String),
+    Apr1 { salt: String, hash: String },
+}
+
+impl Hash {
+    fn parse(value: &str) -> Option<Self> {
+        if value.starts_with("$2a$") || value.starts_with("$2b$") || value.starts_with("$2y$") {
+            Some(Self::Bcrypt(value.to_string()))
+        } else if value.starts_with("$5$") {
+            Some(Self::Sha256(value.to_string()))
+        } else if value.starts_with("$6$") {
+            Some(Self::Sha512(value.to_string()))
+        } else if let Some(rest) = value.strip_prefix("$apr1$") {
+            let (salt, hash) = rest.split_once('$')?;
+
+            Some(Self::Apr1 {
+                salt: salt.to_string(),
+                hash: hash.to_string(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            Self::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            Self::Sha256(hash) => sha_crypt::sha256_check(password, hash).is_ok(),
+            Self::Sha512(hash) => sha_crypt::sha512_check(password, hash).is_ok(),
+            Self::Apr1 { salt, hash } => apr1(password, salt)
+                .as_bytes()
+                .ct_eq(hash.as_bytes())
+                .into(),
+        }
+    }
+}
+
+/// Compute APR1 variant of MD5-crypt hash, without magic and salt prefix.
+fn apr1(password: &str, salt: &str) -> String {
+    const MAGIC: &[u8] = b"$apr1$";
+
+    let (password, salt) = (password.as_bytes(), &salt.as_bytes()[..salt.len().min(8)]);
+
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut ctx = Md5::new()
+        .chain_update(password)
+        .chain_update(MAGIC)
+        .chain_update(salt);
+    for chunk in password.chunks(16) {
+        ctx.update(&alternate[..chunk.len()]);
+    }
+    let mut i = password.len();
+    while i > 0 {
+        if i & 1 == 1 {
+            ctx.update([0]);
+        } else {
+            ctx.update(&password[..1]);
+        }
+        i >>= 1;
+    }
+    let mut digest = ctx.finalize();
+
+    // Stretch to slow down brute force
+    for i in 0..1000 {
+        let mut ctx = Md5::new();
+        if i & 1 == 1 {
+            ctx.update(password);
+        } else {
+            ctx.update(digest);
+        }
+        if i % 3 != 0 {
+            ctx.update(salt);
+        }
+        if i % 7 != 0 {
+            ctx.update(password);
+        }
+        if i & 1 == 1 {
+            ctx.update(digest);
+        } else {
+            ctx.update(password);
+        }
+        digest = ctx.finalize();
+    }
+
+    let mut encoded = String::with_capacity(22);
+    let mut push = |value: u32, n: usize| {
+        for k in 0..n {
+            encoded.push(CRYPT_ALPHABET[((value >> (6 * k)) & 0x3f) as usize] as char);
+        }
+    };
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        push(
+            (digest[a] as u32) << 16 | (digest[b] as u32) << 8 | digest[c] as u32,
+            4,
+        );
+    }
+    push(digest[11] as u32, 2);
+
+    encoded
+}
+
+#[derive(Debug)]
+struct Users {
+    hashes: HashMap<String, Hash>,
+
+    /// Hash verified for unknown users, so that they take as long to reject as known ones. One of file's hashes, to
+    /// share algorithm and cost with them.
+    dummy: Hash,
+
+    /// Modification time of file when loaded.
+    modified: Option<SystemTime>,
+}
+
+/// HTTP basic authenticator verifying users against htpasswd file. Supports bcrypt, SHA-256/512-crypt and APR1-MD5
+/// hashes; file is reloaded when its modification time changes, checked at most once per refresh interval.
+#[derive(Debug)]
+pub struct Htpasswd {
+    path: PathBuf,
+    users: RwLock<Users>,
+
+    /// Minimum time between checks of file for changes.
+    refresh_interval: Duration,
+
+    /// When file was last checked for changes.
+    checked: Mutex<Instant>,
+}
+
+impl Htpasswd {
+    pub fn new<P>(path: P) -> Result<Self, std::io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let users = Self::load(&path)?;
+
+        Ok(Self {
+            path,
+            users: RwLock::new(users),
+            refresh_interval: Duration::from_secs(5),
+            checked: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Set minimum time between checks of file for changes, 5 seconds by default.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    fn load(path: &Path) -> Result<Users, std::io::Error> {
+        let modified = std::fs::metadata(path)?.modified().ok();
+        let content = std::fs::read_to_string(path)?;
+
+        let mut hashes = HashMap::new();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line
+                .split_once(':')
+                .and_then(|(user, value)| Hash::parse(value).map(|hash| (user.to_string(), hash)))
+            {
+                Some((user, hash)) => {
+                    hashes.insert(user, hash);
+                }
+                None => warn!(
+                    "skipping unsupported entry at line {n} of {path}",
+                    n = n + 1,
+                    path = path.display()
+                ),
+            }
+        }
+
+        let dummy = hashes
+            .values()
+            .next()
+            .cloned()
+            .or_else(|| Hash::parse(DUMMY_HASH))
+            .unwrap();
+
+        Ok(Users {
+            hashes,
+            dummy,
+            modified,
+        })
+    }
+
+    /// Reload users if refresh interval has passed and file has changed since last load. On failure, previously loaded
+    /// users stay in effect.
+    async fn refresh(&self) {
+        {
+            let mut checked = self.checked.lock().unwrap();
+            if checked.elapsed() < self.refresh_interval {
+                return;
+            }
+            *checked = Instant::now();
+        }
+
+        let modified = tokio::fs::metadata(&self.path)
+            .await
+            .and_then(|meta| meta.modified())
+            .ok();
+        if modified.is_none() || modified == self.users.read().unwrap().modified {
+            return;
+        }
+
+        let path = self.path.clone();
+        let loaded = tokio::task::spawn_blocking(move || Self::load(&path))
+            .await
+            .unwrap_or_else(|err| Err(std::io::Error::new(std::io::ErrorKind::Other, err)));
+        match loaded {
+            Ok(users) => {
+                info!(
+                    "reloaded {n} users from {path}",
+                    n = users.hashes.len(),
+                    path = self.path.display()
+                );
+                *self.users.write().unwrap() = users;
+            }
+            Err(err) => warn!("failed to reload {path}: {err}", path = self.path.display()),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for Htpasswd {
+    async fn authenticate(
+        &self,
+        _flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let credentials = credentials.ok_or(Error::MissingCredentials)?;
+        let (username, password) = basic(credentials)?;
+
+        self.refresh().await;
+        let (hash, known) = {
+            let users = self.users.read().unwrap();
+            match users.hashes.get(&username) {
+                Some(hash) => (hash.clone(), true),
+                None => (users.dummy.clone(), false),
+            }
+        };
+
+        // Hashing is deliberately slow, keep it off async workers. Unknown users are verified against dummy hash
+        // nonetheless, so that response time does not tell whether user exists.
+        let verified = tokio::task::spawn_blocking(move || hash.verify(&password))
+            .await
+            .unwrap_or(false);
+        if !known {
+            debug!("unknown user {username}");
+            return Err(Error::NotAuthenticated);
+        }
+        if verified {
+            return Ok(Principal::new(username));
+        }
+
+        Err(Error::NotAuthenticated)
+    }
+
+    fn challenge(&self) -> Option<Challenge> {
+        Some(Challenge::new("Basic"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use anyhow::Result;
+
+    use super::{apr1, Hash, Htpasswd};
+    use crate::auth::{tests::flow, Authenticator, Credentials, Error};
+
+    fn basic(username: &str, password: &str) -> Credentials {
+        Credentials::new(
+            "Basic".to_string(),
+            base64::encode(format!("{username}:{password}")),
+        )
+    }
+
+    #[test]
+    fn apr1_hash() {
+        assert_eq!(apr1("password", "r31Ab"), "jFulDKa3BDYBCpcQYliA20");
+    }
+
+    #[test]
+    fn verify() {
+        for value in [
+            "$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20",
+            "$5$saltsalt$gOjOtoMpVhru2uyjeJSEc/JaLQWOXMNmlOnj6T4AtC.",
+            "$6$saltsalt$qFmFH.bQmmtXzyBY0s9v7Oicd2z4XSIecDzlB5KiA2/jctKu9YterLp8wwnSq.qc.eoxqOmSuNp2xS0ktL3nh/",
+        ] {
+            let hash = Hash::parse(value).unwrap();
+            assert!(hash.verify("password"), "{value}");
+            assert!(!hash.verify("drowssap"), "{value}");
+        }
+
+        assert_eq!(Hash::parse("{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="), None);
+    }
+
+    #[tokio::test]
+    async fn authenticate() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("kkowa-htpasswd-{pid}", pid = std::process::id()));
+        fs::write(
+            &path,
+            format!(
+                "# users\nalice:{bcrypt}\nbob:$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20\n",
+                bcrypt = bcrypt::hash("secret", 4)?
+            ),
+        )?;
+        let auth = Htpasswd::new(&path)?.refresh_interval(Duration::ZERO);
+
+        assert_eq!(
+            auth.authenticate(&flow(), Some(&basic("alice", "secret")))
+                .await?
+                .id,
+            "alice"
+        );
+        assert_eq!(
+            auth.authenticate(&flow(), Some(&basic("bob", "password")))
+                .await?
+                .id,
+            "bob"
+        );
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&basic("bob", "secret")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&basic("carol", "secret")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        // Modification time resolution may be coarse
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        fs::write(&path, "carol:$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20\n")?;
+        assert_eq!(
+            auth.authenticate(&flow(), Some(&basic("carol", "password")))
+                .await?
+                .id,
+            "carol"
+        );
+        assert!(auth
+            .authenticate(&flow(), Some(&basic("alice", "secret")))
+            .await
+            .is_err());
+
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_user() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "kkowa-htpasswd-empty-{pid}",
+            pid = std::process::id()
+        ));
+        fs::write(&path, "# no users\n")?;
+        let auth = Htpasswd::new(&path)?;
+
+        // Password of dummy hash does not let anyone in
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&basic("mallory", "password")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_interval() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "kkowa-htpasswd-interval-{pid}",
+            pid = std::process::id()
+        ));
+        fs::write(&path, "bob:$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20\n")?;
+        let auth = Htpasswd::new(&path)?.refresh_interval(Duration::from_secs(3600));
+
+        // File is not checked again until interval passes
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        fs::write(&path, "carol:$apr1$r31Ab$jFulDKa3BDYBCpcQYliA20\n")?;
+        assert!(auth
+            .authenticate(&flow(), Some(&basic("bob", "password")))
+            .await
+            .is_ok());
+        assert!(auth
+            .authenticate(&flow(), Some(&basic("carol", "password")))
+            .await
+            .is_err());
+
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
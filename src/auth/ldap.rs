@@ -0,0 +1,439 @@
+//! LDAP directory authenticator for HTTP basic credentials.
+
+use std::{fmt::Debug,
+          sync::{Arc, Mutex},
+          time::Duration};
+
+use async_trait::async_trait;
+use ldap3::{ldap_escape, Ldap as Connection, LdapConnAsync, LdapConnSettings, LdapError, Scope,
+            SearchEntry};
+use tracing::{debug, trace, warn};
+use zeroize::Zeroizing;
+
+use super::{basic, cache::TtlMap, Authenticator, Challenge, Credentials, Error, Principal};
+use crate::{http::digest, proxy::Flow};
+
+/// Result code of bind with wrong password.
+///
+/// https://www.rfc-editor.org/rfc/rfc4511#appendix-A.1
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Directory of users, as seen by [`Ldap`] authenticator.
+#[async_trait]
+pub trait Directory: Debug + Send + Sync {
+    /// Find distinguished name of user entry with given username.
+    async fn search(&self, username: &str) -> Result<Option<String>, Error>;
+
+    /// Verify password by binding as user, returning whether password is correct.
+    async fn bind(&self, dn: &str, password: &str) -> Result<bool, Error>;
+
+    /// Names of groups user belongs to.
+    async fn groups(&self, dn: &str) -> Result<Vec<String>, Error>;
+}
+
+impl From<LdapError> for Error {
+    fn from(err: LdapError) -> Self {
+        Self::Backend(err.to_string())
+    }
+}
+
+/// LDAP server accessed via service account, which searches users and their groups over pooled connections. Users
+/// are verified by simple bind over dedicated connections.
+///
+/// Passwords are sent in clear over plain `ldap://` URLs; use `ldaps://` URL or enable StartTLS for anything but
+/// local servers.
+#[derive(Debug)]
+pub struct LdapDirectory {
+    url: String,
+    timeout: Duration,
+
+    /// Whether to upgrade `ldap://` connections with StartTLS.
+    starttls: bool,
+
+    /// TLS configuration, verifying against system certificate store if unset.
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+
+    /// Service account searching users and groups.
+    bind_dn: String,
+    bind_password: String,
+
+    /// Base DN and filter of user search; `{username}` in filter is replaced with escaped username.
+    user_base: String,
+    user_filter: String,
+
+    /// Base DN and filter of group search; `{dn}` in filter is replaced with escaped user DN.
+    group_base: String,
+    group_filter: String,
+
+    /// Attribute of group entries holding group name.
+    group_attr: String,
+
+    /// Idle service account connections.
+    pool: Mutex<Vec<Connection>>,
+    pool_size: usize,
+}
+
+impl LdapDirectory {
+    /// Create new directory searching users by `uid` and groups by `member` under given base DN.
+    pub fn new<S>(url: S, bind_dn: S, bind_password: S, base: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            url: url.as_ref().to_string(),
+            timeout: Duration::from_secs(5),
+            starttls: false,
+            tls_config: None,
+            bind_dn: bind_dn.as_ref().to_string(),
+            bind_password: bind_password.as_ref().to_string(),
+            user_base: base.as_ref().to_string(),
+            user_filter: "(uid={username})".to_string(),
+            group_base: base.as_ref().to_string(),
+            group_filter: "(member={dn})".to_string(),
+            group_attr: "cn".to_string(),
+            pool: Mutex::new(vec![]),
+            pool_size: 4,
+        }
+    }
+
+    pub fn user_search<S>(mut self, base: S, filter: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.user_base = base.as_ref().to_string();
+        self.user_filter = filter.as_ref().to_string();
+        self
+    }
+
+    pub fn group_search<S>(mut self, base: S, filter: S, attr: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.group_base = base.as_ref().to_string();
+        self.group_filter = filter.as_ref().to_string();
+        self.group_attr = attr.as_ref().to_string();
+        self
+    }
+
+    /// Timeout of establishing connections.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Upgrade `ldap://` connections with StartTLS, failing if server does not support it.
+    pub fn starttls(mut self, starttls: bool) -> Self {
+        self.starttls = starttls;
+        self
+    }
+
+    /// TLS configuration of `ldaps://` and StartTLS connections, such as for private certificate authority.
+    pub fn tls_config(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls_config = Some(config);
+        self
+    }
+
+    /// Maximum number of idle service account connections kept.
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool_size = size;
+        self
+    }
+
+    async fn connect(&self) -> Result<Connection, LdapError> {
+        let mut settings = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout)
+            .set_starttls(self.starttls);
+        if let Some(config) = &self.tls_config {
+            settings = settings.set_config(Arc::clone(config));
+        }
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.url).await?;
+        ldap3::drive!(conn);
+
+        Ok(ldap)
+    }
+
+    /// Take idle service account connection or open new one.
+    async fn checkout(&self) -> Result<Connection, LdapError> {
+        loop {
+            let idle = self.pool.lock().unwrap().pop();
+            match idle {
+                Some(mut ldap) => {
+                    if !ldap.is_closed() {
+                        return Ok(ldap);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        let mut ldap = self.connect().await?;
+        ldap.simple_bind(&self.bind_dn, &self.bind_password)
+            .await?
+            .success()?;
+
+        Ok(ldap)
+    }
+
+    fn checkin(&self, ldap: Connection) {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < self.pool_size {
+            pool.push(ldap);
+        }
+    }
+
+    /// Search with service account, returning matching entries.
+    async fn find(
+        &self,
+        base: &str,
+        filter: &str,
+        attrs: Vec<&str>,
+    ) -> Result<Vec<SearchEntry>, LdapError> {
+        let mut ldap = self.checkout().await?;
+        let (entries, _) = ldap
+            .search(base, Scope::Subtree, filter, attrs)
+            .await?
+            .success()?;
+        self.checkin(ldap);
+
+        Ok(entries.into_iter().map(SearchEntry::construct).collect())
+    }
+}
+
+#[async_trait]
+impl Directory for LdapDirectory {
+    async fn search(&self, username: &str) -> Result<Option<String>, Error> {
+        let filter = self
+            .user_filter
+            .replace("{username}", &ldap_escape(username));
+        let entries = self.find(&self.user_base, &filter, vec!["1.1"]).await?;
+        if entries.len() > 1 {
+            warn!("username {username} matches multiple entries");
+            return Ok(None);
+        }
+
+        Ok(entries.into_iter().next().map(|entry| entry.dn))
+    }
+
+    async fn bind(&self, dn: &str, password: &str) -> Result<bool, Error> {
+        let mut ldap = self.connect().await?;
+        let result = ldap.simple_bind(dn, password).await?;
+        let _ = ldap.unbind().await;
+
+        match result.rc {
+            0 => Ok(true),
+            INVALID_CREDENTIALS => Ok(false),
+            _ => Err(result.success().unwrap_err().into()),
+        }
+    }
+
+    async fn groups(&self, dn: &str) -> Result<Vec<String>, Error> {
+        let filter = self.group_filter.replace("{dn}", &ldap_escape(dn));
+        let entries = self
+            .find(&self.group_base, &filter, vec![self.group_attr.as_str()])
+            .await?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|mut entry| entry.attrs.remove(&self.group_attr))
+            .flatten()
+            .collect())
+    }
+}
+
+/// HTTP basic authenticator verifying users against directory with search-then-bind. Groups of user are set on
+/// authenticated identity, and successful logins are cached for configured TTL.
+#[derive(Debug)]
+pub struct Ldap {
+    directory: Box<dyn Directory>,
+    cache_ttl: Duration,
+    cache: Mutex<TtlMap<Principal>>,
+}
+
+impl Ldap {
+    /// Create new authenticator caching up to 10 000 successful logins for 1 minute.
+    pub fn new<D>(directory: D) -> Self
+    where
+        D: Directory + 'static,
+    {
+        Self {
+            directory: Box::new(directory),
+            cache_ttl: Duration::from_secs(60),
+            cache: Mutex::new(TtlMap::new(10_000)),
+        }
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for Ldap {
+    async fn authenticate(
+        &self,
+        _flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let credentials = credentials.ok_or(Error::MissingCredentials)?;
+        let (username, password) = basic(credentials)?;
+
+        // Empty password would make unauthenticated bind, which servers accept
+        // https://www.rfc-editor.org/rfc/rfc4513#section-5.1.2
+        if password.is_empty() {
+            return Err(Error::NotAuthenticated);
+        }
+
+        let key = digest(
+            Zeroizing::new(format!(
+                "{username}:{password}",
+                password = password.as_str()
+            ))
+            .as_bytes(),
+        );
+        let cached = self.cache.lock().unwrap().get(&key);
+        if let Some(principal) = cached {
+            trace!("LDAP login cache hit for {username}");
+            return Ok(principal);
+        }
+
+        let Some(dn) = self.directory.search(&username).await? else {
+            debug!("user {username} not found in directory");
+            return Err(Error::NotAuthenticated);
+        };
+        if !self.directory.bind(&dn, &password).await? {
+            debug!("bind as {dn} failed");
+            return Err(Error::NotAuthenticated);
+        }
+        let groups = self.directory.groups(&dn).await?;
+
+        let mut builder = Principal::builder();
+        builder.id(username).claim("dn", dn);
+        for group in groups {
+            builder.group(group);
+        }
+        let principal = builder
+            .build()
+            .map_err(|err| Error::Backend(err.to_string()))?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, principal.clone(), self.cache_ttl);
+
+        Ok(principal)
+    }
+
+    fn challenge(&self) -> Option<Challenge> {
+        Some(Challenge::new("Basic"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap,
+              sync::{atomic::{AtomicUsize, Ordering},
+                     Arc}};
+
+    use async_trait::async_trait;
+
+    use super::{Directory, Ldap};
+    use crate::auth::{tests::flow, Authenticator, Credentials, Error};
+
+    /// In-memory directory standing in for LDAP server.
+    #[derive(Debug, Default)]
+    struct Memory {
+        /// Password and groups by DN.
+        entries: HashMap<String, (String, Vec<String>)>,
+        binds: Arc<AtomicUsize>,
+    }
+
+    impl Memory {
+        fn with_user(mut self, uid: &str, password: &str, groups: &[&str]) -> Self {
+            self.entries.insert(
+                format!("uid={uid},ou=people,dc=example,dc=com"),
+                (
+                    password.to_string(),
+                    groups.iter().map(|g| g.to_string()).collect(),
+                ),
+            );
+            self
+        }
+    }
+
+    #[async_trait]
+    impl Directory for Memory {
+        async fn search(&self, username: &str) -> Result<Option<String>, Error> {
+            let dn = format!("uid={username},ou=people,dc=example,dc=com");
+
+            Ok(self.entries.contains_key(&dn).then_some(dn))
+        }
+
+        async fn bind(&self, dn: &str, password: &str) -> Result<bool, Error> {
+            self.binds.fetch_add(1, Ordering::SeqCst);
+
+            Ok(self.entries.get(dn).map_or(false, |(p, _)| p == password))
+        }
+
+        async fn groups(&self, dn: &str) -> Result<Vec<String>, Error> {
+            Ok(self
+                .entries
+                .get(dn)
+                .map(|(_, groups)| groups.clone())
+                .unwrap_or_default())
+        }
+    }
+
+    fn basic(username: &str, password: &str) -> Credentials {
+        Credentials::new(
+            "Basic".to_string(),
+            base64::encode(format!("{username}:{password}")),
+        )
+    }
+
+    #[tokio::test]
+    async fn authenticate() {
+        let directory = Memory::default().with_user("jdoe", "secret", &["developers"]);
+        let binds = Arc::clone(&directory.binds);
+        let auth = Ldap::new(directory);
+
+        for _ in 0..2 {
+            let principal = auth
+                .authenticate(&flow(), Some(&basic("jdoe", "secret")))
+                .await
+                .unwrap();
+            assert_eq!(principal.id, "jdoe");
+            assert!(principal.in_group("developers"));
+            assert_eq!(
+                principal.claims["dn"],
+                "uid=jdoe,ou=people,dc=example,dc=com"
+            );
+        }
+
+        // Second login served from cache
+        assert_eq!(binds.load(Ordering::SeqCst), 1);
+
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&basic("jdoe", "wrong")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&basic("nobody", "secret")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+    }
+
+    #[tokio::test]
+    async fn authenticate_empty_password() {
+        let directory = Memory::default().with_user("jdoe", "", &[]);
+        let binds = Arc::clone(&directory.binds);
+        let auth = Ldap::new(directory);
+
+        assert!(auth
+            .authenticate(&flow(), Some(&basic("jdoe", "")))
+            .await
+            .is_err());
+        assert_eq!(binds.load(Ordering::SeqCst), 0);
+    }
+}
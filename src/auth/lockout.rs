@@ -0,0 +1,299 @@
+//! Brute-force protection for authenticators.
+
+use std::{collections::HashMap,
+          net::IpAddr,
+          sync::Mutex,
+          time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use tracing::{trace, warn};
+
+use super::{basic, Authenticator, Challenge, Credentials, Error, Principal};
+use crate::{metrics, proxy::Flow};
+
+/// Subject failures are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Client(IpAddr),
+
+    /// Username tried from client address. Not counted per bare username, so that others cannot lock user out.
+    User(IpAddr, String),
+}
+
+#[derive(Debug)]
+struct Attempts {
+    /// Failures within current window.
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Wrapper locking out username tried from client address, for HTTP basic credentials, after too many failed
+/// attempts within window; client address is locked out as whole after more failures across usernames. Locked out
+/// subjects are rejected as not authenticated without consulting inner authenticator until lockout elapses.
+///
+/// Only rejected credentials count as failures; missing credentials and backend errors do not.
+#[derive(Debug)]
+pub struct Lockout<A> {
+    inner: A,
+    max_failures: u32,
+    max_client_failures: u32,
+    window: Duration,
+    duration: Duration,
+    attempts: Mutex<HashMap<Key, Attempts>>,
+}
+
+impl<A> Lockout<A>
+where
+    A: Authenticator,
+{
+    /// Create new lockout over authenticator, locking out for 15 minutes after 5 failures for username or 20 failures
+    /// for client address within 5 minutes.
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            max_failures: 5,
+            max_client_failures: 20,
+            window: Duration::from_secs(300),
+            duration: Duration::from_secs(900),
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Failures for username from client address before locking it out.
+    pub fn max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Failures from client address, across usernames, before locking whole address out.
+    pub fn max_client_failures(mut self, max_failures: u32) -> Self {
+        self.max_client_failures = max_failures;
+        self
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// How long subject stays locked out.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    fn keys(flow: &Flow, credentials: Option<&Credentials>) -> Vec<Key> {
+        let ip = flow.client().ip();
+        let mut keys = vec![Key::Client(ip)];
+        if let Some((username, _)) = credentials.and_then(|c| basic(c).ok()) {
+            keys.push(Key::User(ip, username));
+        }
+
+        keys
+    }
+
+    fn is_locked(&self, keys: &[Key], now: Instant) -> bool {
+        let attempts = self.attempts.lock().unwrap();
+        keys.iter().any(|key| {
+            attempts
+                .get(key)
+                .and_then(|a| a.locked_until)
+                .map_or(false, |until| until > now)
+        })
+    }
+
+    fn fail(&self, keys: Vec<Key>, now: Instant) {
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.retain(|_, a| {
+            a.locked_until.map_or(false, |until| until > now) || a.window_start + self.window > now
+        });
+
+        for key in keys {
+            let a = attempts.entry(key.clone()).or_insert(Attempts {
+                failures: 0,
+                window_start: now,
+                locked_until: None,
+            });
+            if a.window_start + self.window <= now {
+                a.failures = 0;
+                a.window_start = now;
+            }
+            a.failures += 1;
+
+            let max_failures = match key {
+                Key::Client(_) => self.max_client_failures,
+                Key::User(..) => self.max_failures,
+            };
+            if a.failures >= max_failures {
+                warn!(
+                    "locking out {key:?} after {n} failed attempts",
+                    n = a.failures
+                );
+                metrics::AUTH_LOCKOUT_COUNTER.increment(1);
+                a.failures = 0;
+                a.locked_until = Some(now + self.duration);
+            }
+        }
+    }
+
+    /// Forget failures for username; failures of client address stay, so that one valid account does not reset them.
+    fn succeed(&self, keys: &[Key]) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for key in keys.iter().filter(|key| matches!(key, Key::User(..))) {
+            attempts.remove(key);
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Authenticator for Lockout<A>
+where
+    A: Authenticator + Send + Sync,
+{
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let keys = Self::keys(flow, credentials);
+        if self.is_locked(&keys, Instant::now()) {
+            trace!("rejecting locked out {keys:?}");
+            metrics::AUTH_LOCKED_OUT_COUNTER.increment(1);
+            return Err(Error::NotAuthenticated);
+        }
+
+        match self.inner.authenticate(flow, credentials).await {
+            Ok(principal) => {
+                self.succeed(&keys);
+                Ok(principal)
+            }
+            Err(Error::NotAuthenticated) => {
+                metrics::AUTH_FAILURE_COUNTER.increment(1);
+                self.fail(keys, Instant::now());
+                Err(Error::NotAuthenticated)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn challenge(&self) -> Option<Challenge> {
+        self.inner.challenge()
+    }
+
+    fn challenges(&self) -> Vec<Challenge> {
+        self.inner.challenges()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::Lockout;
+    use crate::{auth::{tests::flow, Authenticator, Credentials, Error, HTTPBasic},
+                proxy::Proxy};
+
+    fn credentials(username: &str, password: &str) -> Credentials {
+        Credentials::new("Basic", &base64::encode(format!("{username}:{password}")))
+    }
+
+    #[tokio::test]
+    async fn authenticate() -> Result<()> {
+        let auth = Lockout::new(HTTPBasic::new("username", "password")).max_failures(3);
+        let valid = credentials("username", "password");
+        let invalid = credentials("username", "guess");
+
+        // Success resets failures
+        for _ in 0..2 {
+            auth.authenticate(&flow(), Some(&invalid))
+                .await
+                .unwrap_err();
+        }
+        auth.authenticate(&flow(), Some(&valid)).await?;
+        for _ in 0..2 {
+            assert!(matches!(
+                auth.authenticate(&flow(), Some(&invalid)).await,
+                Err(Error::NotAuthenticated)
+            ));
+        }
+
+        // Third failure in row locks user out from client, even with valid credentials
+        auth.authenticate(&flow(), Some(&invalid))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&valid)).await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        // Other clients are not affected
+        let other = Proxy::default().flow("10.0.0.1:1234".parse()?);
+        auth.authenticate(&other, Some(&valid)).await?;
+
+        // Missing credentials are not failures
+        let auth = Lockout::new(HTTPBasic::new("username", "password")).max_failures(1);
+        for _ in 0..2 {
+            assert!(matches!(
+                auth.authenticate(&flow(), None).await,
+                Err(Error::MissingCredentials)
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authenticate_client() -> Result<()> {
+        let auth = Lockout::new(HTTPBasic::new("username", "password"))
+            .max_failures(2)
+            .max_client_failures(3);
+
+        // Failures across usernames lock client out
+        for username in ["alice", "bob", "carol"] {
+            assert!(matches!(
+                auth.authenticate(&flow(), Some(&credentials(username, "guess")))
+                    .await,
+                Err(Error::NotAuthenticated)
+            ));
+        }
+        assert!(matches!(
+            auth.authenticate(&flow(), Some(&credentials("username", "password")))
+                .await,
+            Err(Error::NotAuthenticated)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authenticate_expiry() -> Result<()> {
+        let valid = credentials("username", "password");
+        let invalid = credentials("username", "guess");
+
+        // Lockout elapses
+        let auth = Lockout::new(HTTPBasic::new("username", "password"))
+            .max_failures(1)
+            .duration(Duration::ZERO);
+        auth.authenticate(&flow(), Some(&invalid))
+            .await
+            .unwrap_err();
+        auth.authenticate(&flow(), Some(&valid)).await?;
+
+        // Failures outside window are forgotten
+        let auth = Lockout::new(HTTPBasic::new("username", "password"))
+            .max_failures(2)
+            .window(Duration::ZERO);
+        for _ in 0..3 {
+            assert!(matches!(
+                auth.authenticate(&flow(), Some(&invalid)).await,
+                Err(Error::NotAuthenticated)
+            ));
+        }
+        auth.authenticate(&flow(), Some(&valid)).await?;
+
+        Ok(())
+    }
+}
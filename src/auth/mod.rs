@@ -1,12 +1,38 @@
-mod credentials;
+mod cache;
+mod certificate;
+mod chain;
+mod challenge;
+mod cidr;
+pub(crate) mod credentials;
+mod digest;
+mod htpasswd;
+mod introspection;
+mod jwt;
+mod ldap;
+mod lockout;
+mod principal;
 
 use std::fmt::Debug;
 
 use async_trait::async_trait;
 use thiserror::Error;
-use tracing::{debug, trace};
+use tracing::trace;
+use zeroize::Zeroizing;
 
-pub use self::credentials::Credentials;
+pub use self::{cache::Cached,
+               certificate::{ClientCertificate, SubjectField},
+               chain::{AllOf, AnyOf, FirstMatch},
+               challenge::{Challenge, Param},
+               cidr::{CidrAllowlist, Network, ParseNetworkError},
+               credentials::Credentials,
+               digest::{Algorithm, Digest},
+               htpasswd::Htpasswd,
+               introspection::Introspection,
+               jwt::Jwt,
+               ldap::{Directory, Ldap, LdapDirectory},
+               lockout::Lockout,
+               principal::{Principal, PrincipalBuilder}};
+use crate::proxy::Flow;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -16,13 +42,78 @@ pub enum Error {
     #[error("provided auth credentials data is wrong format")]
     InvalidFormat { n: usize },
 
+    #[error("no credentials provided")]
+    MissingCredentials,
+
     #[error("authentication failed")]
     NotAuthenticated,
+
+    /// Credentials are valid but were computed with expired nonce, so client should retry with new one.
+    #[error("nonce has expired")]
+    StaleNonce,
+
+    #[error("authentication backend failed: {0}")]
+    Backend(String),
+
+    /// Client is explicitly refused, other authenticators must not be tried.
+    #[error("access denied: {0}")]
+    Denied(String),
+}
+
+/// Authentication requirement of requests, see [`Proxy`](crate::Proxy) routes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// Unauthenticated requests are challenged.
+    Required,
+
+    /// Requests are authenticated if possible, but let through otherwise.
+    Optional,
+
+    /// Requests are not authenticated at all.
+    Public,
+}
+
+impl Default for Requirement {
+    fn default() -> Self {
+        Self::Required
+    }
 }
 
 #[async_trait]
 pub trait Authenticator: Debug {
-    async fn authenticate(&self, credentials: &Credentials) -> Result<(), Error>;
+    /// Authenticate client of flow with credentials, if client provided any, returning identity of authenticated
+    /// user.
+    async fn authenticate(
+        &self,
+        flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error>;
+
+    /// Challenge sent in `Proxy-Authenticate` header when authentication is required, if authenticator expects
+    /// credentials.
+    fn challenge(&self) -> Option<Challenge> {
+        None
+    }
+
+    /// All challenges sent on behalf of authenticator; combinators override this to collect those of inner ones.
+    fn challenges(&self) -> Vec<Challenge> {
+        self.challenge().into_iter().collect()
+    }
+}
+
+/// Extract username and password from HTTP basic credentials.
+pub(crate) fn basic(credentials: &Credentials) -> Result<(String, Zeroizing<String>), Error> {
+    credentials.basic().map_err(|err| match err {
+        credentials::Error::UnexpectedScheme(got) => {
+            trace!("scheme expected \"basic\" but got \"{got}\"");
+            Error::InvalidScheme {
+                got,
+                expect: "basic".to_string(),
+            }
+        }
+        credentials::Error::InvalidFormat { n } => Error::InvalidFormat { n },
+        _ => Error::InvalidFormat { n: 0 },
+    })
 }
 
 /// Simple static HTTP basic authenticator.
@@ -46,43 +137,27 @@ pub fn new<S>(username: S, password: S) -> Self
 
 #[async_trait]
 impl Authenticator for HTTPBasic {
-    async fn authenticate(&self, credentials: &Credentials) -> Result<(), Error> {
-        if credentials.scheme().to_lowercase() != "basic" {
-            trace!(
-                "scheme expected \"basic\" but got \"{got}\"",
-                got = credentials.scheme()
-            );
-            return Err(Error::InvalidScheme {
-                got: credentials.scheme().to_string(),
-                expect: "basic".to_string(),
-            });
-        }
-
-        // Base64 Decode credential field as this scheme expects base64 encoded credentials
-        // in format of "<username>:<password>"
-        let decoded = base64::decode(credentials.credentials())
-            .expect("failed to base64 decode HTTP basic credentials");
-        let decoded = String::from_utf8_lossy(&decoded);
-        let v: Vec<&str> = decoded.split_terminator(':').collect();
-
-        let n = v.len();
-        if n != 2 {
-            debug!(
-                "credentials data contains {n} fields separate by colon, while expecting only 2"
-            );
-            return Err(Error::InvalidFormat { n });
-        }
-
-        let (username, password) = (v[0].to_string(), v[1].to_string());
-        if username == self.username && password == self.password {
-            return Ok(());
+    async fn authenticate(
+        &self,
+        _flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let credentials = credentials.ok_or(Error::MissingCredentials)?;
+        let (username, password) = basic(credentials)?;
+        if username == self.username && *password == self.password {
+            return Ok(Principal::new(username));
         }
 
         Err(Error::NotAuthenticated)
     }
+
+    fn challenge(&self) -> Option<Challenge> {
+        Some(Challenge::new("Basic"))
+    }
 }
 
-/// Simple static HTTP bearer authenticator.
+/// Simple static HTTP bearer authenticator. As token carries no identity, authenticated user is identified as
+/// `"bearer"`.
 #[derive(Debug)]
 pub struct HTTPBearer {
     token: String,
@@ -101,8 +176,13 @@ pub fn new<S>(token: S) -> Self
 
 #[async_trait]
 impl Authenticator for HTTPBearer {
-    async fn authenticate(&self, credentials: &Credentials) -> Result<(), Error> {
-        if credentials.scheme().to_lowercase() != "bearer" {
+    async fn authenticate(
+        &self,
+        _flow: &Flow,
+        credentials: Option<&Credentials>,
+    ) -> Result<Principal, Error> {
+        let credentials = credentials.ok_or(Error::MissingCredentials)?;
+        if !credentials.is_scheme("bearer") {
             trace!(
                 "scheme expected \"bearer\" but got \"{got}\"",
                 got = credentials.scheme()
@@ -113,32 +193,50 @@ async fn authenticate(&self, credentials: &Credentials) -> Result<(), Error> {
             });
         }
 
-        if *credentials.credentials() == self.token {
-            return Ok(());
+        if credentials.credentials() == self.token {
+            return Ok(Principal::new("bearer"));
         }
 
         Err(Error::NotAuthenticated)
     }
+
+    fn challenge(&self) -> Option<Challenge> {
+        Some(Challenge::new("Bearer"))
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::{Credentials, Error, HTTPBasic, HTTPBearer};
-    use crate::auth::Authenticator;
+    use crate::{auth::Authenticator,
+                proxy::{Flow, Proxy}};
+
+    /// Flow of local client, for testing authenticators.
+    pub(crate) fn flow() -> Flow {
+        Proxy::default().flow("127.0.0.1:65535".parse().unwrap())
+    }
 
     #[tokio::test]
     async fn httpbasic() {
-        assert!(HTTPBasic::new("username", "password")
-            .authenticate(&Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ=")) // username:password
+        let principal = HTTPBasic::new("username", "password")
+            .authenticate(
+                &flow(),
+                Some(&Credentials::new("Basic", "dXNlcm5hbWU6cGFzc3dvcmQ=")),
+            ) // username:password
             .await
-            .is_ok());
+            .unwrap();
+
+        assert_eq!(principal.id, "username");
     }
 
     #[tokio::test]
     async fn httpbasic_invalid_scheme() {
         assert!(matches!(
             HTTPBasic::new("username", "password")
-                .authenticate(&Credentials::new("Base", "dXNlcm5hbWU6cGFzc3dvcmQ=")) // username:password
+                .authenticate(
+                    &flow(),
+                    Some(&Credentials::new("Base", "dXNlcm5hbWU6cGFzc3dvcmQ="))
+                ) // username:password
                 .await,
             Err(Error::InvalidScheme { .. }) // FIXME: Can't try match using string types
         ));
@@ -148,9 +246,9 @@ async fn httpbasic_invalid_scheme() {
     async fn httpbasic_invalid_format() {
         assert!(matches!(
             HTTPBasic::new("username", "password")
-                .authenticate(&Credentials::new("Basic", "b25lOnR3bzp0aHJlZQ==")) // one:two:three
+                .authenticate(&flow(), Some(&Credentials::new("Basic", "dXNlcm5hbWU="))) // username
                 .await,
-            Err(Error::InvalidFormat { n: 3 })
+            Err(Error::InvalidFormat { n: 1 })
         ));
     }
 
@@ -158,7 +256,10 @@ async fn httpbasic_invalid_format() {
     async fn httpbasic_unauthenticated() {
         assert!(matches!(
             HTTPBasic::new("username", "password")
-                .authenticate(&Credentials::new("Basic", "cGFzc3dvcmQ6dXNlcm5hbWU=")) // password:username
+                .authenticate(
+                    &flow(),
+                    Some(&Credentials::new("Basic", "cGFzc3dvcmQ6dXNlcm5hbWU="))
+                ) // password:username
                 .await,
             Err(Error::NotAuthenticated)
         ));
@@ -167,7 +268,7 @@ async fn httpbasic_unauthenticated() {
     #[tokio::test]
     async fn httpbearer() {
         assert!(HTTPBearer::new("token")
-            .authenticate(&Credentials::new("Bearer", "token"))
+            .authenticate(&flow(), Some(&Credentials::new("Bearer", "token")))
             .await
             .is_ok());
     }
@@ -176,7 +277,7 @@ async fn httpbearer() {
     async fn httpbearer_invalid_scheme() {
         assert!(matches!(
             HTTPBearer::new("token")
-                .authenticate(&Credentials::new("Token", "token"))
+                .authenticate(&flow(), Some(&Credentials::new("Token", "token")))
                 .await,
             Err(Error::InvalidScheme { .. }) // FIXME: Can't try match using string types
         ));
@@ -186,7 +287,7 @@ async fn httpbearer_invalid_scheme() {
     async fn httpbearer_unauthenticated() {
         assert!(matches!(
             HTTPBearer::new("token")
-                .authenticate(&Credentials::new("Bearer", "nekot"))
+                .authenticate(&flow(), Some(&Credentials::new("Bearer", "nekot")))
                 .await,
             Err(Error::NotAuthenticated)
         ));
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use derive_builder::Builder;
+use serde_json::Value;
+
+/// Identity of authenticated proxy user.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Builder)]
+#[builder(default)]
+pub struct Principal {
+    /// Unique user identifier, such as username or subject.
+    #[builder(setter(into))]
+    pub id: String,
+
+    /// Human-readable name for display.
+    #[builder(setter(into, strip_option))]
+    pub name: Option<String>,
+
+    /// Groups or roles user belongs to.
+    #[builder(setter(each(name = "group", into)))]
+    pub groups: Vec<String>,
+
+    /// Arbitrary claims provided by authenticator.
+    #[builder(setter(custom))]
+    pub claims: HashMap<String, Value>,
+
+    /// Time after which identity must not be trusted anymore.
+    #[builder(setter(strip_option))]
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Principal {
+    /// Create new principal with given identifier only.
+    pub fn new<S>(id: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            id: id.as_ref().to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn builder() -> PrincipalBuilder {
+        PrincipalBuilder::default()
+    }
+
+    /// Whether user belongs to given group.
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.map_or(false, |exp| exp <= now)
+    }
+}
+
+impl PrincipalBuilder {
+    pub fn claim<K, V>(&mut self, key: K, value: V) -> &mut Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.claims
+            .get_or_insert_with(HashMap::default)
+            .insert(key.into(), value.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::Result;
+    use serde_json::json;
+
+    use super::Principal;
+
+    #[test]
+    fn builder() -> Result<()> {
+        let now = SystemTime::now();
+        let principal = Principal::builder()
+            .id("jdoe")
+            .name("John Doe")
+            .group("developers")
+            .claim("email", "jdoe@example.com")
+            .expires_at(now)
+            .build()?;
+
+        assert_eq!(principal.id, "jdoe");
+        assert_eq!(principal.name.as_deref(), Some("John Doe"));
+        assert!(principal.in_group("developers"));
+        assert!(!principal.in_group("admins"));
+        assert_eq!(principal.claims["email"], json!("jdoe@example.com"));
+        assert!(principal.is_expired(now));
+        assert!(!principal.is_expired(now - Duration::from_secs(1)));
+
+        Ok(())
+    }
+}
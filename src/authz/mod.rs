@@ -0,0 +1,202 @@
+//! Authorization of authenticated proxy users.
+
+mod rule;
+
+use std::{fs,
+          path::{Path, PathBuf},
+          sync::RwLock,
+          time::SystemTime};
+
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::info;
+
+pub use self::rule::{Effect, Rule, TimeOfDay, TimeWindow};
+use crate::{auth::Principal, http::Request};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read policy file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to parse policy file: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("policy is not loaded from file")]
+    NoSource,
+}
+
+/// Outcome of policy evaluation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny { reason: String },
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Self::Allow)
+    }
+}
+
+/// Serialized form of policy, as stored in file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Rules {
+    /// Effect applied when no rule matches.
+    pub default: Effect,
+
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// Ordered set of access rules; first matching rule decides.
+#[derive(Debug)]
+pub struct Policy {
+    rules: RwLock<Rules>,
+
+    /// File policy loaded from, used to reload rules.
+    path: Option<PathBuf>,
+}
+
+impl Policy {
+    pub fn new(default: Effect, rules: Vec<Rule>) -> Self {
+        Self {
+            rules: RwLock::new(Rules { default, rules }),
+            path: None,
+        }
+    }
+
+    /// Load policy from JSON file.
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let rules = Self::read(&path)?;
+
+        Ok(Self {
+            rules: RwLock::new(rules),
+            path: Some(path),
+        })
+    }
+
+    /// Reload rules from file policy loaded from. On failure, current rules stay in effect.
+    pub fn reload(&self) -> Result<(), Error> {
+        let path = self.path.as_ref().ok_or(Error::NoSource)?;
+        let rules = Self::read(path)?;
+        info!(
+            "reloaded {n} authorization rules from {path}",
+            n = rules.rules.len(),
+            path = path.display()
+        );
+        *self.rules.write().unwrap() = rules;
+
+        Ok(())
+    }
+
+    fn read(path: &Path) -> Result<Rules, Error> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Decide whether principal, if any, may send given request.
+    pub fn evaluate(
+        &self,
+        principal: Option<&Principal>,
+        req: &Request,
+        now: SystemTime,
+    ) -> Decision {
+        let rules = self.rules.read().unwrap();
+        let (effect, reason) = match rules
+            .rules
+            .iter()
+            .find(|rule| rule.matches(principal, req, now))
+        {
+            Some(rule) => (rule.effect, rule.reason.clone()),
+            None => (rules.default, None),
+        };
+
+        match effect {
+            Effect::Allow => Decision::Allow,
+            Effect::Deny => Decision::Deny {
+                reason: reason.unwrap_or_else(|| "access denied by proxy policy".to_string()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use anyhow::Result;
+
+    use super::{Decision, Effect, Error, Policy};
+    use crate::{auth::Principal, http::Request};
+
+    const POLICY: &str = r#"{
+        "default": "deny",
+        "rules": [
+            { "effect": "deny", "hosts": ["*.internal"], "reason": "internal hosts are off limits" },
+            { "effect": "allow", "groups": ["developers"] },
+            { "effect": "allow", "users": ["jdoe"], "methods": ["GET"], "time": { "start": "09:00", "end": "18:00" } }
+        ]
+    }"#;
+
+    #[test]
+    fn evaluate() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("kkowa-authz-{pid}.json", pid = std::process::id()));
+        std::fs::write(&path, POLICY)?;
+        let policy = Policy::from_file(&path)?;
+
+        let developer = Principal::builder()
+            .id("alice")
+            .group("developers")
+            .build()?;
+        let jdoe = Principal::new("jdoe");
+        let req = Request::builder()
+            .uri("http://example.com/".parse()?)
+            .build()?;
+        let internal = Request::builder()
+            .uri("http://db.internal/".parse()?)
+            .build()?;
+
+        assert_eq!(
+            policy.evaluate(Some(&developer), &req, UNIX_EPOCH),
+            Decision::Allow
+        );
+        assert_eq!(
+            policy.evaluate(Some(&developer), &internal, UNIX_EPOCH),
+            Decision::Deny {
+                reason: "internal hosts are off limits".to_string()
+            }
+        );
+        assert!(!policy.evaluate(Some(&jdoe), &req, UNIX_EPOCH).is_allowed());
+        assert!(policy
+            .evaluate(
+                Some(&jdoe),
+                &req,
+                UNIX_EPOCH + std::time::Duration::from_secs(10 * 3600)
+            )
+            .is_allowed());
+        assert!(!policy.evaluate(None, &req, UNIX_EPOCH).is_allowed());
+
+        std::fs::write(&path, r#"{ "default": "allow" }"#)?;
+        policy.reload()?;
+        assert!(policy.evaluate(None, &req, UNIX_EPOCH).is_allowed());
+
+        // Source file has gone, rules stay in effect
+        std::fs::remove_file(&path)?;
+        assert!(matches!(policy.reload(), Err(Error::Io(_))));
+        assert!(policy.evaluate(None, &req, UNIX_EPOCH).is_allowed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reload_without_source() {
+        let policy = Policy::new(Effect::Allow, vec![]);
+
+        assert!(matches!(policy.reload(), Err(Error::NoSource)));
+    }
+}
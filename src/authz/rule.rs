@@ -0,0 +1,239 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::{auth::Principal,
+            http::{Method, Request},
+            proxy::glob};
+
+/// Whether matching rule grants or denies access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// Time of day in UTC, as minutes since midnight. Deserialized from `"HH:MM"` string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    pub fn at(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Self(((secs % 86400) / 60) as u16)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time of day {value:?}, expected HH:MM");
+        let (h, m) = value.split_once(':').ok_or_else(invalid)?;
+        let (h, m): (u16, u16) = (
+            h.parse().map_err(|_| invalid())?,
+            m.parse().map_err(|_| invalid())?,
+        );
+        if h > 23 || m > 59 {
+            return Err(invalid());
+        }
+
+        Ok(Self(h * 60 + m))
+    }
+}
+
+/// Time window in UTC, inclusive start and exclusive end. Window wraps around midnight if start is after end.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct TimeWindow {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Access rule. Empty condition lists match anything.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Rule {
+    pub effect: Effect,
+
+    /// User IDs of principal.
+    #[serde(default)]
+    pub users: Vec<String>,
+
+    /// Groups of principal; matches if principal belongs to any.
+    #[serde(default)]
+    pub groups: Vec<String>,
+
+    /// Destination host patterns, may contain `*` wildcards.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+
+    /// Destination ports.
+    #[serde(default)]
+    pub ports: Vec<u16>,
+
+    /// Request methods, such as `GET` or `CONNECT`.
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    /// Time window when rule applies.
+    #[serde(default)]
+    pub time: Option<TimeWindow>,
+
+    /// Reason reported to client when rule denies access.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, principal: Option<&Principal>, req: &Request, now: SystemTime) -> bool {
+        if !self.users.is_empty() && !principal.map_or(false, |p| self.users.contains(&p.id)) {
+            return false;
+        }
+
+        if !self.groups.is_empty()
+            && !principal.map_or(false, |p| self.groups.iter().any(|g| p.in_group(g)))
+        {
+            return false;
+        }
+
+        if !self.hosts.is_empty() {
+            let host = req.uri.host().unwrap_or_default().to_lowercase();
+            if !self
+                .hosts
+                .iter()
+                .any(|pattern| glob(&pattern.to_lowercase(), &host))
+            {
+                return false;
+            }
+        }
+
+        if !self.ports.is_empty() && !self.ports.contains(&port(req)) {
+            return false;
+        }
+
+        if !self.methods.is_empty()
+            && !self
+                .methods
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(req.method.as_str()))
+        {
+            return false;
+        }
+
+        if let Some(window) = &self.time {
+            if !window.contains(TimeOfDay::at(now)) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Destination port of request, defaulting by scheme.
+fn port(req: &Request) -> u16 {
+    req.uri.port_u16().unwrap_or_else(|| {
+        if req.method == Method::CONNECT || req.uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use anyhow::Result;
+
+    use super::{Effect, Rule, TimeOfDay, TimeWindow};
+    use crate::{auth::Principal,
+                http::{Method, Request}};
+
+    fn rule() -> Rule {
+        Rule {
+            effect: Effect::Allow,
+            users: vec![],
+            groups: vec![],
+            hosts: vec![],
+            ports: vec![],
+            methods: vec![],
+            time: None,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(TimeOfDay::try_from("09:30".to_string()), Ok(TimeOfDay(570)));
+        assert!(TimeOfDay::try_from("24:00".to_string()).is_err());
+        assert!(TimeOfDay::try_from("0930".to_string()).is_err());
+        assert_eq!(
+            TimeOfDay::at(UNIX_EPOCH + Duration::from_secs(86400 * 3 + 3600 + 120)),
+            TimeOfDay(62)
+        );
+    }
+
+    #[test]
+    fn time_window_wraps_midnight() {
+        let window = TimeWindow {
+            start: TimeOfDay(22 * 60),
+            end: TimeOfDay(6 * 60),
+        };
+
+        assert!(window.contains(TimeOfDay(23 * 60)));
+        assert!(window.contains(TimeOfDay(60)));
+        assert!(!window.contains(TimeOfDay(12 * 60)));
+    }
+
+    #[test]
+    fn matches() -> Result<()> {
+        let principal = Principal::builder()
+            .id("jdoe")
+            .group("developers")
+            .build()?;
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .uri("api.example.com:443".parse()?)
+            .build()?;
+        let now = UNIX_EPOCH;
+
+        assert!(rule().matches(None, &req, now));
+        assert!(Rule {
+            groups: vec!["developers".to_string()],
+            hosts: vec!["*.example.com".to_string()],
+            ports: vec![443],
+            methods: vec!["connect".to_string()],
+            ..rule()
+        }
+        .matches(Some(&principal), &req, now));
+        assert!(!Rule {
+            users: vec!["jdoe".to_string()],
+            ..rule()
+        }
+        .matches(None, &req, now));
+        assert!(!Rule {
+            ports: vec![80],
+            ..rule()
+        }
+        .matches(Some(&principal), &req, now));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Parser for `Cache-Control` header directives.
+
+use std::time::Duration;
+
+use crate::http::{header, Headers};
+
+/// Parsed `Cache-Control` directives, of either request or response.
+///
+/// https://www.rfc-editor.org/rfc/rfc9111#section-5.2
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool,
+    pub only_if_cached: bool,
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+    pub min_fresh: Option<Duration>,
+
+    /// `Some(None)` if `max-stale` given without value, which means any staleness is acceptable.
+    pub max_stale: Option<Option<Duration>>,
+}
+
+impl CacheControl {
+    /// Parse all `Cache-Control` headers in given header map. Unknown or malformed directives are ignored.
+    pub fn parse(headers: &Headers) -> Self {
+        let mut cc = Self::default();
+
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            let Ok(value) = value.to_str() else { continue };
+
+            for directive in value.split(',') {
+                let mut kv = directive.trim().splitn(2, '=');
+                let name = kv.next().unwrap_or_default().trim().to_lowercase();
+                let arg = kv.next().map(|v| v.trim().trim_matches('"'));
+                let seconds = || {
+                    arg.and_then(|v| v.parse::<u64>().ok())
+                        .map(Duration::from_secs)
+                };
+
+                match name.as_str() {
+                    "no-store" => cc.no_store = true,
+                    "no-cache" => cc.no_cache = true,
+                    "private" => cc.private = true,
+                    "public" => cc.public = true,
+                    "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                    "only-if-cached" => cc.only_if_cached = true,
+                    "max-age" => cc.max_age = seconds(),
+                    "s-maxage" => cc.s_maxage = seconds(),
+                    "min-fresh" => cc.min_fresh = seconds(),
+                    "max-stale" => cc.max_stale = Some(seconds()),
+                    _ => {}
+                }
+            }
+        }
+
+        cc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::CacheControl;
+    use crate::http::{header, Headers};
+
+    #[test]
+    fn parse() -> Result<()> {
+        let mut headers = Headers::new();
+        headers.append(header::CACHE_CONTROL, "public, max-age=60".parse()?);
+        headers.append(
+            header::CACHE_CONTROL,
+            "S-MAXAGE=\"120\", max-stale, unknown=1, must-revalidate".parse()?,
+        );
+
+        assert_eq!(
+            CacheControl::parse(&headers),
+            CacheControl {
+                public: true,
+                must_revalidate: true,
+                max_age: Some(Duration::from_secs(60)),
+                s_maxage: Some(Duration::from_secs(120)),
+                max_stale: Some(None),
+                ..Default::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(
+            CacheControl::parse(&Headers::new()),
+            CacheControl::default()
+        );
+    }
+}
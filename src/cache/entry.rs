@@ -0,0 +1,470 @@
+//! Stored response and its freshness calculation.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::control::CacheControl;
+use crate::http::{header, HeaderName, HeaderValue, Headers, Request, Response, StatusCode};
+
+/// Status codes heuristically cacheable by default.
+///
+/// https://www.rfc-editor.org/rfc/rfc9110#section-15.1
+const HEURISTICALLY_CACHEABLE: &[u16] =
+    &[200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Cached response with metadata required for freshness calculation and `Vary` matching.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub response: Response,
+
+    /// Values of request headers nominated by response `Vary` header, at the time of storing.
+    pub variant: Vec<(String, Option<String>)>,
+
+    /// Time when response was received.
+    pub stored_at: SystemTime,
+}
+
+impl Entry {
+    pub fn new(req: &Request, response: Response, now: SystemTime) -> Self {
+        Self {
+            variant: variant(&response.headers, &req.headers),
+            response,
+            stored_at: now,
+        }
+    }
+
+    /// Approximate size of entry in bytes, used for storage limits.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .response
+            .headers
+            .iter()
+            .map(|(k, v)| k.as_str().len() + v.len())
+            .sum();
+
+        self.response.payload.len() + headers
+    }
+
+    /// Whether given request selects this entry, according to stored `Vary` header values.
+    pub fn matches_variant(&self, req: &Request) -> bool {
+        self.variant_of(req) == self.variant
+    }
+
+    /// Values of request headers nominated by this entry's `Vary` header, normalised for comparison.
+    pub fn variant_of(&self, req: &Request) -> Vec<(String, Option<String>)> {
+        variant(&self.response.headers, &req.headers)
+    }
+
+    /// Whether `304 Not Modified` response to revalidation of this entry selects it for update. Response without
+    /// validators selects entry, as revalidation request carried entry's own.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-4.3.4
+    pub fn is_selected_by(&self, not_modified: &Response) -> bool {
+        let (stored, headers) = (&self.response.headers, &not_modified.headers);
+        if let Some(etag) = headers.get(header::ETAG) {
+            let weak = |tag: &HeaderValue| {
+                tag.as_bytes()
+                    .strip_prefix(b"W/")
+                    .unwrap_or(tag.as_bytes())
+                    .to_vec()
+            };
+
+            return stored
+                .get(header::ETAG)
+                .map_or(false, |stored| weak(stored) == weak(etag));
+        }
+        if let Some(last_modified) = headers.get(header::LAST_MODIFIED) {
+            return stored.get(header::LAST_MODIFIED) == Some(last_modified);
+        }
+
+        true
+    }
+
+    /// Current age of entry.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-4.2.3
+    pub fn age(&self, now: SystemTime) -> Duration {
+        let age_value = self
+            .response
+            .headers
+            .get(header::AGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let apparent_age = date(&self.response.headers, header::DATE)
+            .and_then(|date| self.stored_at.duration_since(date).ok())
+            .unwrap_or_default();
+        let resident_time = now.duration_since(self.stored_at).unwrap_or_default();
+
+        apparent_age.max(age_value) + resident_time
+    }
+
+    /// Freshness lifetime of entry, for shared cache.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-4.2.1
+    pub fn freshness_lifetime(&self) -> Duration {
+        let headers = &self.response.headers;
+        let cc = CacheControl::parse(headers);
+
+        if let Some(lifetime) = cc.s_maxage.or(cc.max_age) {
+            return lifetime;
+        }
+
+        let date_value = date(headers, header::DATE).unwrap_or(self.stored_at);
+        if let Some(expires) = date_or_past(headers, header::EXPIRES) {
+            return expires.duration_since(date_value).unwrap_or_default();
+        }
+
+        // Heuristic freshness; 10% of time since last modification
+        if HEURISTICALLY_CACHEABLE.contains(&self.response.status.as_u16()) {
+            if let Some(last_modified) = date(headers, header::LAST_MODIFIED) {
+                return date_value.duration_since(last_modified).unwrap_or_default() / 10;
+            }
+        }
+
+        Duration::ZERO
+    }
+
+    /// Whether entry can be served without revalidation for request.
+    pub fn is_fresh(&self, req: &Request, now: SystemTime) -> bool {
+        let req_cc = CacheControl::parse(&req.headers);
+        let resp_cc = CacheControl::parse(&self.response.headers);
+        if req_cc.no_cache || resp_cc.no_cache {
+            return false;
+        }
+
+        let age = self.age(now);
+        let mut lifetime = self.freshness_lifetime();
+        if let Some(max_age) = req_cc.max_age {
+            lifetime = lifetime.min(max_age);
+        }
+        if let Some(min_fresh) = req_cc.min_fresh {
+            lifetime = lifetime.saturating_sub(min_fresh);
+        }
+
+        if age < lifetime {
+            return true;
+        }
+
+        // Stale; client may accept it unless response forbids
+        match req_cc.max_stale {
+            Some(_) if resp_cc.must_revalidate => false,
+            Some(None) => true,
+            Some(Some(max_stale)) => age < lifetime + max_stale,
+            None => false,
+        }
+    }
+
+    /// Whether entry has validators usable for conditional request.
+    pub fn has_validators(&self) -> bool {
+        let headers = &self.response.headers;
+
+        headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED)
+    }
+
+    /// Update stored headers with ones from `304 Not Modified` response.
+    ///
+    /// https://www.rfc-editor.org/rfc/rfc9111#section-3.2
+    pub fn refresh(&mut self, not_modified: &Response, now: SystemTime) {
+        for name in not_modified.headers.keys() {
+            if *name == header::CONTENT_LENGTH {
+                continue;
+            }
+
+            self.response.headers.remove(name);
+            for value in not_modified.headers.get_all(name) {
+                self.response.headers.append(name.clone(), value.clone());
+            }
+        }
+        self.stored_at = now;
+    }
+}
+
+/// Whether response for request may be stored by shared cache.
+///
+/// https://www.rfc-editor.org/rfc/rfc9111#section-3
+pub fn is_storable(req: &Request, resp: &Response) -> bool {
+    let req_cc = CacheControl::parse(&req.headers);
+    let resp_cc = CacheControl::parse(&resp.headers);
+
+    if req.method != crate::http::Method::GET || req_cc.no_store {
+        return false;
+    }
+    if matches!(
+        resp.status,
+        StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+    ) || resp.status.is_informational()
+    {
+        return false;
+    }
+    if resp_cc.no_store || resp_cc.private {
+        return false;
+    }
+    if resp
+        .headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim() == "*")
+    {
+        return false;
+    }
+    if req.headers.contains_key(header::AUTHORIZATION)
+        && !(resp_cc.public || resp_cc.s_maxage.is_some() || resp_cc.must_revalidate)
+    {
+        return false;
+    }
+
+    // Must have explicit freshness or be heuristically cacheable
+    resp_cc.public
+        || resp_cc.max_age.is_some()
+        || resp_cc.s_maxage.is_some()
+        || resp.headers.contains_key(header::EXPIRES)
+        || HEURISTICALLY_CACHEABLE.contains(&resp.status.as_u16())
+}
+
+/// Collect request header values nominated by `Vary` response header.
+fn variant(resp_headers: &Headers, req_headers: &Headers) -> Vec<(String, Option<String>)> {
+    let mut names: Vec<String> = resp_headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| {
+            // Whitespace around list items is insignificant
+            let value = req_headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.split(',').map(str::trim).collect::<Vec<_>>().join(","));
+
+            (name, value)
+        })
+        .collect()
+}
+
+fn date(headers: &Headers, name: HeaderName) -> Option<SystemTime> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+}
+
+/// Parse date header, treating invalid values (such as `Expires: 0`) as in the past.
+fn date_or_past(headers: &Headers, name: HeaderName) -> Option<SystemTime> {
+    headers
+        .get(&name)
+        .map(|_| date(headers, name).unwrap_or(SystemTime::UNIX_EPOCH))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use anyhow::Result;
+
+    use super::Entry;
+    use crate::http::{header, Request, Response, StatusCode};
+
+    fn response(headers: &[(header::HeaderName, &str)]) -> Result<Response> {
+        let mut builder = Response::builder();
+        for (k, v) in headers {
+            builder.header(k.clone(), v.parse()?);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    #[test]
+    fn freshness_lifetime() -> Result<()> {
+        let now = SystemTime::now();
+        let date = httpdate::fmt_http_date(now);
+
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[(header::CACHE_CONTROL, "max-age=60, s-maxage=120")])?,
+            now,
+        );
+        assert_eq!(entry.freshness_lifetime(), Duration::from_secs(120));
+
+        let expires = httpdate::fmt_http_date(now + Duration::from_secs(30));
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[(header::DATE, &date), (header::EXPIRES, &expires)])?,
+            now,
+        );
+        assert!(entry.freshness_lifetime() <= Duration::from_secs(30));
+        assert!(entry.freshness_lifetime() >= Duration::from_secs(29));
+
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[(header::EXPIRES, "0")])?,
+            now,
+        );
+        assert_eq!(entry.freshness_lifetime(), Duration::ZERO);
+
+        let last_modified = httpdate::fmt_http_date(now - Duration::from_secs(1000));
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[
+                (header::DATE, &date),
+                (header::LAST_MODIFIED, &last_modified),
+            ])?,
+            now,
+        );
+        assert_eq!(entry.freshness_lifetime(), Duration::from_secs(100));
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_fresh() -> Result<()> {
+        let now = SystemTime::now();
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[(header::CACHE_CONTROL, "max-age=60"), (header::AGE, "30")])?,
+            now,
+        );
+
+        assert!(entry.is_fresh(&Request::default(), now));
+        assert!(!entry.is_fresh(&Request::default(), now + Duration::from_secs(31)));
+
+        let req = Request::builder()
+            .header(header::CACHE_CONTROL, "max-stale=10".parse()?)
+            .build()?;
+        assert!(entry.is_fresh(&req, now + Duration::from_secs(35)));
+
+        let req = Request::builder()
+            .header(header::CACHE_CONTROL, "no-cache".parse()?)
+            .build()?;
+        assert!(!entry.is_fresh(&req, now));
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_storable() -> Result<()> {
+        let req = Request::default();
+
+        assert!(super::is_storable(&req, &response(&[])?));
+        assert!(!super::is_storable(
+            &req,
+            &response(&[(header::CACHE_CONTROL, "no-store")])?
+        ));
+        assert!(!super::is_storable(
+            &req,
+            &response(&[(header::CACHE_CONTROL, "private, max-age=60")])?
+        ));
+        assert!(!super::is_storable(
+            &req,
+            &response(&[(header::VARY, "*")])?
+        ));
+
+        let mut resp = response(&[])?;
+        resp.status = StatusCode::INTERNAL_SERVER_ERROR;
+        assert!(!super::is_storable(&req, &resp));
+
+        let req = Request::builder()
+            .header(header::AUTHORIZATION, "Bearer token".parse()?)
+            .build()?;
+        assert!(!super::is_storable(&req, &response(&[])?));
+        assert!(super::is_storable(
+            &req,
+            &response(&[(header::CACHE_CONTROL, "public")])?
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn matches_variant() -> Result<()> {
+        let req = Request::builder()
+            .header(header::ACCEPT_ENCODING, "gzip".parse()?)
+            .build()?;
+        let entry = Entry::new(
+            &req,
+            response(&[(header::VARY, "Accept-Encoding")])?,
+            SystemTime::now(),
+        );
+
+        assert!(entry.matches_variant(&req));
+        assert!(!entry.matches_variant(&Request::default()));
+
+        let req = Request::builder()
+            .header(header::ACCEPT_ENCODING, "gzip, br".parse()?)
+            .build()?;
+        let entry = Entry::new(
+            &req,
+            response(&[(header::VARY, "Accept-Encoding")])?,
+            SystemTime::now(),
+        );
+        let req = Request::builder()
+            .header(header::ACCEPT_ENCODING, "gzip,br".parse()?)
+            .build()?;
+        assert!(entry.matches_variant(&req));
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_selected_by() -> Result<()> {
+        let last_modified = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(60));
+        let entry = Entry::new(
+            &Request::default(),
+            response(&[
+                (header::ETAG, "W/\"v1\""),
+                (header::LAST_MODIFIED, &last_modified),
+            ])?,
+            SystemTime::now(),
+        );
+
+        assert!(entry.is_selected_by(&response(&[(header::ETAG, "\"v1\"")])?));
+        assert!(!entry.is_selected_by(&response(&[(header::ETAG, "\"v2\"")])?));
+        assert!(entry.is_selected_by(&response(&[(header::LAST_MODIFIED, &last_modified)])?));
+        assert!(!entry.is_selected_by(&response(&[(
+            header::LAST_MODIFIED,
+            &httpdate::fmt_http_date(SystemTime::now())
+        )])?));
+        assert!(entry.is_selected_by(&response(&[])?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh() -> Result<()> {
+        let now = SystemTime::now();
+        let mut entry = Entry::new(
+            &Request::default(),
+            response(&[
+                (header::ETAG, "\"v1\""),
+                (header::CACHE_CONTROL, "max-age=1"),
+            ])?,
+            now - Duration::from_secs(10),
+        );
+
+        entry.refresh(
+            &response(&[
+                (header::CACHE_CONTROL, "max-age=60"),
+                (header::CONTENT_LENGTH, "0"),
+            ])?,
+            now,
+        );
+
+        assert_eq!(entry.response.headers[header::CACHE_CONTROL], "max-age=60");
+        assert_eq!(entry.response.headers[header::ETAG], "\"v1\"");
+        assert!(!entry.response.headers.contains_key(header::CONTENT_LENGTH));
+        assert_eq!(entry.stored_at, now);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! HTTP response cache, as a shared cache described in RFC 9111.
+//!
+//! https://www.rfc-editor.org/rfc/rfc9111
+
+mod control;
+mod entry;
+mod storage;
+
+use std::{collections::{HashMap, HashSet},
+          sync::Mutex,
+          time::SystemTime};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+pub use self::{control::CacheControl,
+               entry::{is_storable, Entry},
+               storage::{Disk, Memory, Storage}};
+use crate::{http::{header, remove_hop_by_hop_headers, HeaderValue, Method, Request, Response,
+                   StatusCode},
+            proxy::{Flow, Forward, Handler, Reverse}};
+
+/// Response header reporting how cache handled request.
+///
+/// https://www.rfc-editor.org/rfc/rfc9211
+pub const CACHE_STATUS: &str = "Cache-Status";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("entry of {0} bytes exceeds storage limit")]
+    TooLarge(usize),
+
+    #[error("failed to access storage: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to (de)serialize entry: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Handler serving cached responses and storing cacheable ones. Only `GET` requests are cached.
+///
+/// Fresh hits are replied directly, stale entries with validators are revalidated with conditional requests and
+/// refreshed on `304 Not Modified`. Served responses carry `Age` and `Cache-Status` headers.
+///
+/// Responses with `Vary` header are stored per variant, so that each variant of URI can be served from cache.
+#[derive(Debug)]
+pub struct Cache {
+    /// Cache name reported in `Cache-Status` header.
+    name: String,
+    storage: Box<dyn Storage>,
+
+    /// Keys of variants stored per URI key, to invalidate them together.
+    variants: Mutex<HashMap<String, HashSet<String>>>,
+
+    /// Flows whose current request is revalidation issued by cache, rather than by client.
+    revalidating: Mutex<HashSet<u64>>,
+}
+
+impl Cache {
+    pub fn new<S>(storage: S) -> Self
+    where
+        S: Storage + 'static,
+    {
+        Self {
+            name: "kkowa".to_string(),
+            storage: Box::new(storage),
+            variants: Mutex::default(),
+            revalidating: Mutex::default(),
+        }
+    }
+
+    /// Set cache name reported in `Cache-Status` header.
+    pub fn name<S>(mut self, name: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.name = name.as_ref().to_string();
+        self
+    }
+
+    fn key(req: &Request) -> String {
+        format!("{method} {uri}", method = Method::GET, uri = req.uri)
+    }
+
+    /// Key of variant of URI, selected by given `Vary` header values.
+    fn variant_key(req: &Request, variant: &[(String, Option<String>)]) -> String {
+        let mut key = Self::key(req);
+        for (name, value) in variant {
+            key.push('\n');
+            key.push_str(name);
+            if let Some(value) = value {
+                key.push_str(": ");
+                key.push_str(value);
+            }
+        }
+
+        key
+    }
+
+    /// Find stored entry selected by request.
+    ///
+    /// Latest response of URI is stored under URI key, telling which request headers select variants; responses with
+    /// `Vary` header are also stored under keys extended with values of those headers.
+    async fn lookup(&self, req: &Request) -> Option<Entry> {
+        let key = Self::key(req);
+        let Some(latest) = self.storage.get(&key).await else {
+            // Variants are unreachable without latest response; forget them
+            self.forget_variants(&key).await;
+            return None;
+        };
+        if latest.matches_variant(req) {
+            return Some(latest);
+        }
+
+        self.storage
+            .get(&Self::variant_key(req, &latest.variant_of(req)))
+            .await
+            .filter(|entry| entry.matches_variant(req))
+    }
+
+    async fn store(&self, req: &Request, resp: &Response, now: SystemTime) {
+        let mut resp = resp.clone();
+        remove_hop_by_hop_headers(&mut resp.headers);
+
+        self.put(req, Entry::new(req, resp, now)).await;
+    }
+
+    /// Store entry under URI key and, if it varies, under its variant key.
+    async fn put(&self, req: &Request, entry: Entry) {
+        let key = Self::key(req);
+        if !entry.variant.is_empty() {
+            let variant_key = Self::variant_key(req, &entry.variant);
+            if let Err(err) = self.storage.put(&variant_key, entry.clone()).await {
+                warn!("failed to store response for {uri}: {err}", uri = req.uri);
+                return;
+            }
+            self.variants
+                .lock()
+                .unwrap()
+                .entry(key.clone())
+                .or_default()
+                .insert(variant_key);
+        }
+
+        if let Err(err) = self.storage.put(&key, entry).await {
+            warn!("failed to store response for {uri}: {err}", uri = req.uri);
+        }
+    }
+
+    /// Remove all stored variants of URI.
+    async fn invalidate(&self, req: &Request) {
+        let key = Self::key(req);
+        if let Err(err) = self.storage.remove(&key).await {
+            warn!("failed to invalidate {uri}: {err}", uri = req.uri);
+        }
+        self.forget_variants(&key).await;
+    }
+
+    async fn forget_variants(&self, key: &str) {
+        let variants = self.variants.lock().unwrap().remove(key);
+        for variant_key in variants.into_iter().flatten() {
+            if let Err(err) = self.storage.remove(&variant_key).await {
+                warn!("failed to remove cache entry: {err}");
+            }
+        }
+    }
+
+    /// Build response for client from stored entry.
+    fn serve(&self, entry: &Entry, req: Request, status: &str, now: SystemTime) -> Response {
+        let mut resp = entry.response.clone();
+        resp.headers
+            .insert(header::AGE, HeaderValue::from(entry.age(now).as_secs()));
+        self.set_status(&mut resp, status);
+        resp.request = req;
+
+        resp
+    }
+
+    fn set_status(&self, resp: &mut Response, status: &str) {
+        let value = format!("{name}; {status}", name = self.name);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            resp.headers.insert(CACHE_STATUS, value);
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for Cache {
+    async fn on_request(&self, flow: &Flow, req: Request) -> Forward {
+        self.revalidating.lock().unwrap().remove(flow.id());
+        if req.method != Method::GET {
+            return Forward::DoNothing;
+        }
+
+        let now = SystemTime::now();
+        let entry = self.lookup(&req).await;
+        let cc = CacheControl::parse(&req.headers);
+
+        match entry {
+            Some(entry) if entry.is_fresh(&req, now) => {
+                debug!("cache hit for {uri}", uri = req.uri);
+                Forward::Reply(Box::new(self.serve(&entry, req, "hit", now)))
+            }
+            _ if cc.only_if_cached => {
+                let mut resp = Response::builder()
+                    .status(StatusCode::GATEWAY_TIMEOUT)
+                    .request(req)
+                    .build()
+                    .unwrap();
+                self.set_status(&mut resp, "fwd=miss");
+
+                Forward::Reply(Box::new(resp))
+            }
+            Some(entry)
+                if entry.has_validators()
+                    && !req.headers.contains_key(header::IF_NONE_MATCH)
+                    && !req.headers.contains_key(header::IF_MODIFIED_SINCE) =>
+            {
+                debug!("revalidating stale entry for {uri}", uri = req.uri);
+                self.revalidating.lock().unwrap().insert(*flow.id());
+                let mut req = req;
+                let stored = &entry.response.headers;
+                if let Some(etag) = stored.get(header::ETAG) {
+                    req.headers.insert(header::IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(last_modified) = stored.get(header::LAST_MODIFIED) {
+                    req.headers
+                        .insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+                }
+
+                Forward::Modify(Box::new(req))
+            }
+            _ => Forward::DoNothing,
+        }
+    }
+
+    async fn on_response(&self, flow: &Flow, resp: Response) -> Reverse {
+        let req = &resp.request;
+        let now = SystemTime::now();
+        let revalidating = self.revalidating.lock().unwrap().remove(flow.id());
+
+        // Unsafe methods invalidate stored response of target URI
+        // https://www.rfc-editor.org/rfc/rfc9111#section-4.4
+        if !req.method.is_safe() {
+            if resp.status.is_success() || resp.status.is_redirection() {
+                self.invalidate(req).await;
+            }
+
+            return Reverse::DoNothing;
+        }
+
+        if req.method != Method::GET {
+            return Reverse::DoNothing;
+        }
+
+        // Only revalidations issued by cache refresh stored entry; client's own conditional requests may carry
+        // validators of other representation, so their 304 is passed through as is
+        if resp.status == StatusCode::NOT_MODIFIED && revalidating {
+            match self.lookup(req).await {
+                Some(mut entry) if entry.is_selected_by(&resp) => {
+                    entry.refresh(&resp, now);
+                    self.put(req, entry.clone()).await;
+                    let served = self.serve(&entry, req.clone(), "fwd=stale; fwd-status=304", now);
+
+                    return Reverse::Modify(Box::new(served));
+                }
+                _ => debug!("304 for {uri} does not match stored entry", uri = req.uri),
+            }
+        }
+
+        let mut resp = resp;
+        if is_storable(&resp.request, &resp) {
+            self.store(&resp.request, &resp, now).await;
+            self.set_status(&mut resp, "fwd=miss; stored");
+        } else {
+            self.set_status(&mut resp, "fwd=miss");
+        }
+
+        Reverse::Modify(Box::new(resp))
+    }
+}
@@ -0,0 +1,281 @@
+//! Storage backends for cache entries.
+
+use std::{collections::HashMap,
+          fmt::Debug,
+          path::{Path, PathBuf},
+          sync::Mutex};
+
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+use super::{entry::Entry, Error};
+use crate::http::digest;
+
+/// Key-value storage of cache entries.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    async fn get(&self, key: &str) -> Option<Entry>;
+
+    async fn put(&self, key: &str, entry: Entry) -> Result<(), Error>;
+
+    async fn remove(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Bookkeeping for least-recently-used eviction under total size limit.
+#[derive(Debug, Default)]
+struct Index {
+    /// Key to entry size and last access tick.
+    items: HashMap<String, (usize, u64)>,
+    size: usize,
+    tick: u64,
+}
+
+impl Index {
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        if let Some(item) = self.items.get_mut(key) {
+            item.1 = self.tick;
+        }
+    }
+
+    fn insert(&mut self, key: &str, size: usize) {
+        self.remove(key);
+        self.tick += 1;
+        self.items.insert(key.to_string(), (size, self.tick));
+        self.size += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((size, _)) = self.items.remove(key) {
+            self.size -= size;
+        }
+    }
+
+    /// Pop least recently used keys until total size fits in limit.
+    fn evict(&mut self, limit: usize) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.size > limit {
+            let Some(key) = self
+                .items
+                .iter()
+                .min_by_key(|(_, (_, tick))| *tick)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.remove(&key);
+            evicted.push(key);
+        }
+
+        evicted
+    }
+}
+
+/// In-memory storage with total size limit in bytes.
+#[derive(Debug)]
+pub struct Memory {
+    limit: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+    index: Mutex<Index>,
+}
+
+impl Memory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            entries: Mutex::new(HashMap::new()),
+            index: Mutex::new(Index::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for Memory {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let entry = self.entries.lock().unwrap().get(key).cloned();
+        if entry.is_some() {
+            self.index.lock().unwrap().touch(key);
+        }
+
+        entry
+    }
+
+    async fn put(&self, key: &str, entry: Entry) -> Result<(), Error> {
+        let size = entry.size();
+        if size > self.limit {
+            return Err(Error::TooLarge(size));
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let mut index = self.index.lock().unwrap();
+        entries.insert(key.to_string(), entry);
+        index.insert(key, size);
+        for key in index.evict(self.limit) {
+            debug!("evicting cache entry {key}");
+            entries.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.entries.lock().unwrap().remove(key);
+        self.index.lock().unwrap().remove(key);
+
+        Ok(())
+    }
+}
+
+/// On-disk storage, one JSON file per entry in given directory, with total size limit in bytes.
+#[derive(Debug)]
+pub struct Disk {
+    dir: PathBuf,
+    limit: usize,
+    index: Mutex<Index>,
+}
+
+impl Disk {
+    /// Open storage at given directory, creating it if not exists. Existing entries are indexed for size limit.
+    pub fn new<P>(dir: P, limit: usize) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut index = Index::default();
+        for item in std::fs::read_dir(&dir)? {
+            let item = item?;
+            let name = item.file_name().to_string_lossy().to_string();
+            if let Some(stem) = name.strip_suffix(".json") {
+                index.insert(stem, item.metadata()?.len() as usize);
+            }
+        }
+
+        let disk = Self {
+            dir,
+            limit,
+            index: Mutex::new(index),
+        };
+        let evicted = disk.index.lock().unwrap().evict(limit);
+        for file in evicted {
+            std::fs::remove_file(disk.path(&file))?;
+        }
+
+        Ok(disk)
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(format!("{file}.json"))
+    }
+}
+
+#[async_trait]
+impl Storage for Disk {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let file = digest(key.as_bytes());
+        let data = tokio::fs::read(self.path(&file)).await.ok()?;
+
+        match serde_json::from_slice(&data) {
+            Ok(entry) => {
+                self.index.lock().unwrap().touch(&file);
+                Some(entry)
+            }
+            Err(err) => {
+                warn!("corrupted cache entry {file}: {err}");
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, entry: Entry) -> Result<(), Error> {
+        let file = digest(key.as_bytes());
+        let data = serde_json::to_vec(&entry)?;
+        if data.len() > self.limit {
+            return Err(Error::TooLarge(data.len()));
+        }
+
+        tokio::fs::write(self.path(&file), &data).await?;
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(&file, data.len());
+            index.evict(self.limit)
+        };
+        for file in evicted {
+            debug!("evicting cache entry {file}");
+            let _ = tokio::fs::remove_file(self.path(&file)).await;
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        let file = digest(key.as_bytes());
+        self.index.lock().unwrap().remove(&file);
+
+        match tokio::fs::remove_file(self.path(&file)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use anyhow::Result;
+
+    use super::{Disk, Memory, Storage};
+    use crate::{cache::{entry::Entry, Error},
+                http::{Request, Response}};
+
+    fn entry(payload: &[u8]) -> Result<Entry> {
+        Ok(Entry::new(
+            &Request::default(),
+            Response::builder().payload(payload.to_vec()).build()?,
+            SystemTime::now(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn memory_evicts_least_recently_used() -> Result<()> {
+        let storage = Memory::new(10);
+        storage.put("a", entry(b"aaaa")?).await?;
+        storage.put("b", entry(b"bbbb")?).await?;
+        storage.get("a").await;
+        storage.put("c", entry(b"cccc")?).await?;
+
+        assert!(storage.get("a").await.is_some());
+        assert!(storage.get("b").await.is_none());
+        assert!(storage.get("c").await.is_some());
+        assert!(matches!(
+            storage.put("d", entry(b"too large to store")?).await,
+            Err(Error::TooLarge(18))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kkowa-cache-{pid}", pid = std::process::id()));
+        let storage = Disk::new(&dir, 1024 * 1024)?;
+        let stored = entry(b"Hello World!")?;
+
+        storage
+            .put("GET http://example.com/", stored.clone())
+            .await?;
+        assert_eq!(storage.get("GET http://example.com/").await, Some(stored));
+
+        // Reopen with small limit evicts existing entries
+        drop(storage);
+        let storage = Disk::new(&dir, 1)?;
+        assert!(storage.get("GET http://example.com/").await.is_none());
+
+        storage.remove("GET http://example.com/").await?;
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
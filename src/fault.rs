@@ -0,0 +1,293 @@
+//! Fault injection for chaos testing, to see how clients cope with misbehaving upstreams.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::Rng;
+use tracing::info;
+
+use crate::{http::{header, HeaderName, HeaderValue, Request, Response, StatusCode},
+            metrics,
+            proxy::{Conditions, Flow, Forward, Handler, Matcher, Reverse}};
+
+/// Fault to inject.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// Hold request for given duration before passing it on.
+    Delay(Duration),
+
+    /// Hold request for random duration between given bounds, inclusive.
+    RandomDelay(Duration, Duration),
+
+    /// Reply with given status instead of forwarding request.
+    Abort(StatusCode),
+
+    /// Close client connection without response.
+    Reset,
+
+    /// Cut response body down to given fraction of it, keeping original `Content-Length`, so that client sees
+    /// connection closed before whole body arrived.
+    Truncate(f64),
+
+    /// Send response body at given bytes per second.
+    SlowDrip(u64),
+
+    /// Replace `Content-Type` with unparsable value and add header with non-UTF-8 value.
+    MalformedHeaders,
+}
+
+impl Fault {
+    /// Whether fault applies to requests, rather than responses.
+    fn is_request(&self) -> bool {
+        matches!(
+            self,
+            Self::Delay(_) | Self::RandomDelay(..) | Self::Abort(_) | Self::Reset
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Delay(_) => "delay",
+            Self::RandomDelay(..) => "random_delay",
+            Self::Abort(_) => "abort",
+            Self::Reset => "reset",
+            Self::Truncate(_) => "truncate",
+            Self::SlowDrip(_) => "slow_drip",
+            Self::MalformedHeaders => "malformed_headers",
+        }
+    }
+
+    async fn on_request(&self, req: Request) -> Forward {
+        match self {
+            Self::Delay(delay) => {
+                tokio::time::sleep(*delay).await;
+                Forward::DoNothing
+            }
+            Self::RandomDelay(min, max) => {
+                let delay = rand::thread_rng().gen_range(*min..=(*max).max(*min));
+                tokio::time::sleep(delay).await;
+                Forward::DoNothing
+            }
+            Self::Abort(status) => Forward::Reply(Box::new(
+                Response::builder()
+                    .status(*status)
+                    .payload(b"fault injected".to_vec())
+                    .request(req)
+                    .build()
+                    .unwrap(),
+            )),
+            Self::Reset => Forward::Abort,
+            _ => Forward::DoNothing,
+        }
+    }
+
+    fn on_response(&self, mut resp: Response) -> Reverse {
+        match self {
+            Self::Truncate(fraction) => {
+                let len = resp.payload.len();
+                if !resp.headers.contains_key(header::CONTENT_LENGTH) {
+                    resp.headers
+                        .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+                }
+                let keep = (len as f64 * fraction.clamp(0.0, 1.0)) as usize;
+
+                Reverse::Truncate(Box::new(resp), keep.min(len.saturating_sub(1)))
+            }
+            Self::SlowDrip(rate) => {
+                Reverse::Throttle(Box::new(resp), Conditions::new().downstream(*rate))
+            }
+            Self::MalformedHeaders => {
+                resp.headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("\\invalid;;charset=="),
+                );
+                resp.headers.insert(
+                    HeaderName::from_static("x-fault"),
+                    HeaderValue::from_bytes(b"\xff\xfe").unwrap(),
+                );
+
+                Reverse::Replace(Box::new(resp))
+            }
+            _ => Reverse::DoNothing,
+        }
+    }
+}
+
+/// Rule injecting fault into matching flows with given probability.
+#[derive(Clone, Debug)]
+struct Rule {
+    matcher: Matcher,
+    probability: f64,
+    fault: Fault,
+}
+
+impl Rule {
+    fn fires(&self, flow: &Flow, req: &Request) -> bool {
+        self.matcher.matches(flow, req) && rand::thread_rng().gen_bool(self.probability)
+    }
+}
+
+/// Handler injecting faults into requests and responses. Rules are tried in order for each phase, and first one
+/// firing applies; request faults (delays, aborts and resets) fire on requests, the others on responses.
+#[derive(Debug, Default)]
+pub struct FaultInjection {
+    rules: Vec<Rule>,
+}
+
+impl FaultInjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject fault into flows matching matcher, with given probability clamped to `[0, 1]`.
+    pub fn rule(mut self, matcher: Matcher, probability: f64, fault: Fault) -> Self {
+        self.rules.push(Rule {
+            matcher,
+            probability: probability.clamp(0.0, 1.0),
+            fault,
+        });
+        self
+    }
+
+    fn fire(&self, flow: &Flow, req: &Request, request: bool) -> Option<&Fault> {
+        let fault = self
+            .rules
+            .iter()
+            .filter(|rule| rule.fault.is_request() == request)
+            .find(|rule| rule.fires(flow, req))
+            .map(|rule| &rule.fault)?;
+        info!(
+            "injecting {fault:?} into flow {id} for {uri}",
+            id = flow.id(),
+            uri = req.uri
+        );
+        metrics::fault_injected(fault.name());
+
+        Some(fault)
+    }
+}
+
+#[async_trait]
+impl Handler for FaultInjection {
+    async fn on_request(&self, flow: &Flow, req: Request) -> Forward {
+        match self.fire(flow, &req, true) {
+            Some(fault) => fault.on_request(req).await,
+            None => Forward::DoNothing,
+        }
+    }
+
+    async fn on_response(&self, flow: &Flow, resp: Response) -> Reverse {
+        match self.fire(flow, &resp.request, false) {
+            Some(fault) => fault.on_response(resp),
+            None => Reverse::DoNothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr,
+              str::FromStr,
+              time::{Duration, Instant}};
+
+    use anyhow::Result;
+
+    use super::{Fault, FaultInjection};
+    use crate::{http::{header, Request, Response, StatusCode},
+                proxy::{Forward, Handler, Matcher, Reverse},
+                Proxy};
+
+    fn response() -> Result<Response> {
+        let req = Request::builder()
+            .uri("http://example.com/".parse()?)
+            .build()?;
+
+        Ok(Response::builder()
+            .payload(b"0123456789".to_vec())
+            .request(req)
+            .build()?)
+    }
+
+    #[tokio::test]
+    async fn on_request() -> Result<()> {
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let req = response()?.request;
+
+        let faults = FaultInjection::new()
+            .rule(Matcher::Any, 0.0, Fault::Reset)
+            .rule(
+                Matcher::Host("example.com".to_string()),
+                1.0,
+                Fault::Abort(StatusCode::SERVICE_UNAVAILABLE),
+            );
+        match faults.on_request(&flow, req.clone()).await {
+            Forward::Reply(resp) => assert_eq!(resp.status, StatusCode::SERVICE_UNAVAILABLE),
+            _ => panic!("request not aborted"),
+        }
+
+        let faults = FaultInjection::new().rule(Matcher::Any, 1.0, Fault::Reset);
+        assert!(matches!(
+            faults.on_request(&flow, req.clone()).await,
+            Forward::Abort
+        ));
+
+        let faults = FaultInjection::new().rule(
+            Matcher::Any,
+            1.0,
+            Fault::RandomDelay(Duration::from_millis(50), Duration::from_millis(100)),
+        );
+        let start = Instant::now();
+        assert!(matches!(
+            faults.on_request(&flow, req.clone()).await,
+            Forward::DoNothing
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // Response faults do not fire on requests
+        let faults = FaultInjection::new().rule(Matcher::Any, 1.0, Fault::Truncate(0.5));
+        assert!(matches!(
+            faults.on_request(&flow, req).await,
+            Forward::DoNothing
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn on_response() -> Result<()> {
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+
+        let faults = FaultInjection::new().rule(Matcher::Any, 1.0, Fault::Truncate(0.5));
+        match faults.on_response(&flow, response()?).await {
+            Reverse::Truncate(resp, len) => {
+                assert_eq!(len, 5);
+                assert_eq!(resp.headers[header::CONTENT_LENGTH], "10");
+            }
+            _ => panic!("response not truncated"),
+        }
+
+        let faults = FaultInjection::new().rule(Matcher::Any, 1.0, Fault::SlowDrip(100));
+        assert!(matches!(
+            faults.on_response(&flow, response()?).await,
+            Reverse::Throttle(..)
+        ));
+
+        let faults = FaultInjection::new().rule(Matcher::Any, 1.0, Fault::MalformedHeaders);
+        match faults.on_response(&flow, response()?).await {
+            Reverse::Replace(resp) => assert!(resp.headers["x-fault"].to_str().is_err()),
+            _ => panic!("headers not malformed"),
+        }
+
+        let faults = FaultInjection::new().rule(
+            Matcher::Host("example.org".to_string()),
+            1.0,
+            Fault::MalformedHeaders,
+        );
+        assert!(matches!(
+            faults.on_response(&flow, response()?).await,
+            Reverse::DoNothing
+        ));
+
+        Ok(())
+    }
+}
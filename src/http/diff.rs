@@ -0,0 +1,203 @@
+//! Structured comparison between two responses.
+
+use serde::Serialize;
+
+use super::{HeaderName, Headers, Response, StatusCode};
+
+/// Difference of a single header between two responses. `None` means the header is absent on that side.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HeaderDiff {
+    pub name: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+/// Difference of two response bodies.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BodyDiff {
+    pub expected_len: usize,
+    pub actual_len: usize,
+
+    /// Byte offset where bodies start to differ.
+    pub offset: usize,
+}
+
+/// Structured difference between expected and actual responses.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Diff {
+    #[serde(with = "status_pair")]
+    pub status: Option<(StatusCode, StatusCode)>,
+    pub headers: Vec<HeaderDiff>,
+    pub body: Option<BodyDiff>,
+}
+
+impl Diff {
+    /// Compare two responses, skipping headers in `ignore_headers`.
+    pub fn between(expected: &Response, actual: &Response, ignore_headers: &[HeaderName]) -> Self {
+        let status = (expected.status != actual.status).then_some((expected.status, actual.status));
+        let headers = diff_headers(&expected.headers, &actual.headers, ignore_headers);
+        let body = (expected.payload != actual.payload).then(|| BodyDiff {
+            expected_len: expected.payload.len(),
+            actual_len: actual.payload.len(),
+            offset: expected
+                .payload
+                .iter()
+                .zip(actual.payload.iter())
+                .take_while(|(a, b)| a == b)
+                .count(),
+        });
+
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// Whether two responses are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none() && self.headers.is_empty() && self.body.is_none()
+    }
+
+    /// One-line human-readable summary, such as `"status 200 -> 500, 2 header(s), body"`.
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "identical".to_string();
+        }
+
+        let mut parts = vec![];
+        if let Some((expected, actual)) = self.status {
+            parts.push(format!(
+                "status {expected} -> {actual}",
+                expected = expected.as_u16(),
+                actual = actual.as_u16()
+            ));
+        }
+        if !self.headers.is_empty() {
+            parts.push(format!("{n} header(s)", n = self.headers.len()));
+        }
+        if self.body.is_some() {
+            parts.push("body".to_string());
+        }
+
+        parts.join(", ")
+    }
+}
+
+fn diff_headers(
+    expected: &Headers,
+    actual: &Headers,
+    ignore_headers: &[HeaderName],
+) -> Vec<HeaderDiff> {
+    let join = |headers: &Headers, name: &HeaderName| {
+        let values: Vec<String> = headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string())
+            .collect();
+
+        (!values.is_empty()).then(|| values.join(", "))
+    };
+
+    let mut names: Vec<&HeaderName> = expected
+        .keys()
+        .chain(actual.keys())
+        .filter(|name| !ignore_headers.contains(name))
+        .collect();
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (expected, actual) = (join(expected, name), join(actual, name));
+
+            (expected != actual).then(|| HeaderDiff {
+                name: name.to_string(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+mod status_pair {
+    use serde::{ser::SerializeTuple, Serializer};
+
+    use super::StatusCode;
+
+    pub fn serialize<S>(
+        value: &Option<(StatusCode, StatusCode)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some((expected, actual)) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&expected.as_u16())?;
+                tuple.serialize_element(&actual.as_u16())?;
+                tuple.end()
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{BodyDiff, Diff, HeaderDiff};
+    use crate::http::{header, Response, StatusCode};
+
+    #[test]
+    fn identical() {
+        let diff = Diff::between(&Response::default(), &Response::default(), &[]);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary(), "identical");
+    }
+
+    #[test]
+    fn between() -> Result<()> {
+        let expected = Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain".parse()?)
+            .header(header::DATE, "Tue, 15 Nov 1994 08:12:31 GMT".parse()?)
+            .payload(b"Hello World!".to_vec())
+            .build()?;
+        let actual = Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header(header::CONTENT_TYPE, "application/json".parse()?)
+            .header(header::DATE, "Wed, 16 Nov 1994 08:12:31 GMT".parse()?)
+            .payload(b"Hello Wor".to_vec())
+            .build()?;
+
+        let diff = Diff::between(&expected, &actual, &[header::DATE]);
+
+        assert_eq!(
+            diff.status,
+            Some((StatusCode::OK, StatusCode::INTERNAL_SERVER_ERROR))
+        );
+        assert_eq!(
+            diff.headers,
+            vec![HeaderDiff {
+                name: "content-type".to_string(),
+                expected: Some("text/plain".to_string()),
+                actual: Some("application/json".to_string()),
+            }]
+        );
+        assert_eq!(
+            diff.body,
+            Some(BodyDiff {
+                expected_len: 12,
+                actual_len: 9,
+                offset: 9,
+            })
+        );
+        assert_eq!(diff.summary(), "status 200 -> 500, 1 header(s), body");
+
+        Ok(())
+    }
+}
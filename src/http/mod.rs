@@ -1,10 +1,12 @@
+pub mod diff;
 pub mod request;
 pub mod response;
 
 pub use http::{header::HeaderName, HeaderMap, HeaderValue};
 pub use hyper::{header, Method, StatusCode, Uri, Version};
+use sha2::{Digest, Sha256};
 
-pub use self::{request::Request, response::Response};
+pub use self::{diff::Diff, request::Request, response::Response};
 
 pub type Headers = HeaderMap<HeaderValue>;
 pub type Payload = Vec<u8>;
@@ -34,10 +36,49 @@ pub fn remove_hop_by_hop_headers(headers: &mut Headers) {
     }
 }
 
+/// Compute hex-encoded SHA-256 digest of given data.
+pub(crate) fn digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Serde helper to (de)serialize payloads as base64 encoded string, for human-readable formats such as JSON.
+pub(crate) mod payload_base64 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Payload;
+
+    pub fn serialize<S>(payload: &Payload, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(payload))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Payload, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+
+        base64::decode(s).map_err(D::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{header, HeaderName, Headers};
 
+    #[test]
+    fn digest() {
+        assert_eq!(
+            super::digest(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
     #[test]
     fn remove_hop_by_hop_headers() {
         let mut headers = Headers::new();
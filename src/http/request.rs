@@ -1,17 +1,27 @@
 use derive_builder::Builder;
+use http::uri::Authority;
+use serde::{Deserialize, Serialize};
 
-use super::{HeaderName, HeaderValue, Headers, Method, Payload, Uri, Version};
+use super::{header, payload_base64, HeaderName, HeaderValue, Headers, Method, Payload, Uri,
+            Version};
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Builder)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Builder, Serialize, Deserialize)]
 #[builder(default)]
 pub struct Request {
+    #[serde(with = "http_serde::method")]
     pub method: Method,
+
+    #[serde(with = "http_serde::uri")]
     pub uri: Uri,
+
+    #[serde(with = "http_serde::version")]
     pub version: Version,
 
     #[builder(setter(custom))]
+    #[serde(with = "http_serde::header_map")]
     pub headers: Headers,
 
+    #[serde(with = "payload_base64")]
     pub payload: Payload,
 }
 
@@ -42,6 +52,21 @@ pub async fn from(req: hyper::Request<hyper::Body>) -> Self {
 
         Self::new(parts.method, parts.uri, parts.version, parts.headers, bytes)
     }
+
+    /// Point request to another authority (host and port), keeping scheme, path and query. `Host` header is updated
+    /// as well if present.
+    pub fn set_authority(&mut self, authority: &Authority) -> Result<(), http::Error> {
+        let mut parts = self.uri.clone().into_parts();
+        parts.authority = Some(authority.clone());
+        self.uri = Uri::from_parts(parts)?;
+
+        if self.headers.contains_key(header::HOST) {
+            self.headers
+                .insert(header::HOST, HeaderValue::from_str(authority.as_str())?);
+        }
+
+        Ok(())
+    }
 }
 
 impl From<Request> for hyper::Request<hyper::Body> {
@@ -79,6 +104,7 @@ mod tests {
     use http::{Method, Uri, Version};
 
     use super::Request;
+    use crate::http::header;
 
     #[tokio::test]
     async fn request_from_hyper() -> Result<()> {
@@ -108,4 +134,19 @@ async fn request_into_hyper() -> Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn set_authority() -> Result<()> {
+        let mut req = Request::builder()
+            .uri("http://example.com/get?a=1".parse()?)
+            .header(header::HOST, "example.com".parse()?)
+            .build()?;
+
+        req.set_authority(&"127.0.0.1:8080".parse()?)?;
+
+        assert_eq!(req.uri, Uri::from_static("http://127.0.0.1:8080/get?a=1"));
+        assert_eq!(req.headers[header::HOST], "127.0.0.1:8080");
+
+        Ok(())
+    }
 }
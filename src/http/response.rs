@@ -1,19 +1,28 @@
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 
-use super::{HeaderName, HeaderValue, Headers, Payload, Request, StatusCode, Version};
+use super::{payload_base64, HeaderName, HeaderValue, Headers, Payload, Request, StatusCode,
+            Version};
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Builder)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Builder, Serialize, Deserialize)]
 #[builder(default)]
 pub struct Response {
+    #[serde(with = "http_serde::status_code")]
     pub status: StatusCode,
+
+    #[serde(with = "http_serde::version")]
     pub version: Version,
 
     #[builder(setter(custom))]
+    #[serde(with = "http_serde::header_map")]
     pub headers: Headers,
 
+    #[serde(with = "payload_base64")]
     pub payload: Payload,
 
-    /// Source request to current response.
+    /// Source request to current response. Not serialized, as containers of responses (e.g. recorded exchanges) are
+    /// expected to store requests by themselves.
+    #[serde(skip)]
     pub request: Request,
 }
 
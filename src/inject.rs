@@ -0,0 +1,391 @@
+//! Upstream credential injection, so that clients never handle shared service credentials themselves.
+
+use std::{collections::HashMap,
+          fmt::Debug,
+          path::{Path, PathBuf},
+          sync::Arc};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::{debug, warn};
+use zeroize::Zeroizing;
+
+use crate::{http::{header, HeaderName, HeaderValue, Request, Response, StatusCode},
+            proxy::{Flow, Forward, Handler, Matcher}};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read secret: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("secret store failed: {0}")]
+    Backend(String),
+}
+
+/// Source of secrets injected into upstream requests, looked up by name on every request so that rotated secrets
+/// take effect without restart.
+#[async_trait]
+pub trait SecretStore: Debug + Send + Sync {
+    /// Get secret by name, `None` if no such secret.
+    async fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, Error>;
+}
+
+/// In-memory secret store, mainly for testing.
+#[derive(Debug, Default)]
+pub struct StaticSecrets {
+    secrets: HashMap<String, Zeroizing<String>>,
+}
+
+impl StaticSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn secret<S>(mut self, name: S, value: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.secrets.insert(
+            name.as_ref().to_string(),
+            Zeroizing::new(value.as_ref().to_string()),
+        );
+        self
+    }
+}
+
+#[async_trait]
+impl SecretStore for StaticSecrets {
+    async fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, Error> {
+        Ok(self.secrets.get(name).cloned())
+    }
+}
+
+/// Secret store reading environment variables, optionally prefixed, such as `KKOWA_SECRET_<name>`.
+#[derive(Debug, Default)]
+pub struct EnvSecrets {
+    prefix: String,
+}
+
+impl EnvSecrets {
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            prefix: prefix.as_ref().to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStore for EnvSecrets {
+    async fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, Error> {
+        Ok(
+            std::env::var(format!("{prefix}{name}", prefix = self.prefix))
+                .ok()
+                .map(Zeroizing::new),
+        )
+    }
+}
+
+/// Secret store reading one file per secret from directory, such as mounted Kubernetes or Docker secrets. Trailing
+/// newline of file is ignored.
+#[derive(Debug)]
+pub struct FileSecrets {
+    dir: PathBuf,
+}
+
+impl FileSecrets {
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStore for FileSecrets {
+    async fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, Error> {
+        // Secret names must not escape directory
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Ok(None);
+        }
+
+        match tokio::fs::read_to_string(self.dir.join(name)).await {
+            Ok(content) => {
+                let content = Zeroizing::new(content);
+                Ok(Some(Zeroizing::new(
+                    content.trim_end_matches(['\r', '\n']).to_string(),
+                )))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Credentials to inject, referring secrets by name.
+#[derive(Clone, Debug)]
+pub enum Injection {
+    /// `Authorization: Basic ...` with given username and password secret.
+    Basic { username: String, password: String },
+
+    /// `Authorization: Bearer ...` with token secret.
+    Bearer { token: String },
+
+    /// Arbitrary header, such as API key, with value secret.
+    Header { name: HeaderName, value: String },
+}
+
+impl Injection {
+    /// Resolve secrets into header to set.
+    async fn resolve(
+        &self,
+        store: &dyn SecretStore,
+    ) -> Result<Option<(HeaderName, HeaderValue)>, Error> {
+        let (name, value) = match self {
+            Self::Basic { username, password } => {
+                let Some(password) = store.get(password).await? else {
+                    return Ok(None);
+                };
+                let encoded = base64::encode(Zeroizing::new(format!(
+                    "{username}:{password}",
+                    password = password.as_str()
+                )));
+                (
+                    header::AUTHORIZATION,
+                    Zeroizing::new(format!("Basic {encoded}")),
+                )
+            }
+            Self::Bearer { token } => {
+                let Some(token) = store.get(token).await? else {
+                    return Ok(None);
+                };
+                (
+                    header::AUTHORIZATION,
+                    Zeroizing::new(format!("Bearer {token}", token = token.as_str())),
+                )
+            }
+            Self::Header { name, value } => {
+                let Some(value) = store.get(value).await? else {
+                    return Ok(None);
+                };
+                (name.clone(), value)
+            }
+        };
+
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| Error::Backend(format!("secret for {name} is not valid header value")))?;
+        value.set_sensitive(true);
+
+        Ok(Some((name, value)))
+    }
+}
+
+/// Handler adding credentials to requests for selected upstreams, replacing any the client sent. First matching rule
+/// applies. Requests whose secret cannot be resolved are rejected with `502 Bad Gateway` rather than sent without
+/// credentials.
+///
+/// Only plain HTTP requests forwarded by proxy pass through handlers, so credentials are injected into those alone and
+/// travel to upstream unencrypted; `CONNECT` tunnels are not intercepted, leaving HTTPS requests within untouched.
+#[derive(Debug)]
+pub struct Inject {
+    store: Arc<dyn SecretStore>,
+    rules: Vec<(Matcher, Injection)>,
+}
+
+impl Inject {
+    pub fn new(store: Arc<dyn SecretStore>) -> Self {
+        Self {
+            store,
+            rules: vec![],
+        }
+    }
+
+    /// Inject credentials into requests matching matcher.
+    pub fn rule(mut self, matcher: Matcher, injection: Injection) -> Self {
+        self.rules.push((matcher, injection));
+        self
+    }
+}
+
+#[async_trait]
+impl Handler for Inject {
+    async fn on_request(&self, flow: &Flow, mut req: Request) -> Forward {
+        let Some((_, injection)) = self
+            .rules
+            .iter()
+            .find(|(matcher, _)| matcher.matches(flow, &req))
+        else {
+            return Forward::DoNothing;
+        };
+
+        match injection.resolve(self.store.as_ref()).await {
+            Ok(Some((name, value))) => {
+                debug!("injecting {name} into request to {uri}", uri = req.uri);
+                req.headers.insert(name, value);
+
+                Forward::Modify(Box::new(req))
+            }
+            Ok(None) => {
+                warn!(
+                    "secret to inject into request to {uri} not found",
+                    uri = req.uri
+                );
+                reject(req)
+            }
+            Err(err) => {
+                warn!("failed to resolve secret for {uri}: {err}", uri = req.uri);
+                reject(req)
+            }
+        }
+    }
+}
+
+/// Reply request with `502 Bad Gateway`, as it cannot be sent without credentials.
+fn reject(req: Request) -> Forward {
+    Forward::Reply(Box::new(
+        Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .payload(b"upstream credentials unavailable".to_vec())
+            .request(req)
+            .build()
+            .unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, str::FromStr, sync::Arc};
+
+    use anyhow::Result;
+
+    use super::{EnvSecrets, FileSecrets, Inject, Injection, SecretStore, StaticSecrets};
+    use crate::{http::{header, HeaderName, Request, StatusCode},
+                proxy::{Forward, Handler, Matcher},
+                Proxy};
+
+    fn request(uri: &str) -> Result<Request> {
+        let mut req = Request::builder().uri(uri.parse()?).build()?;
+        req.headers
+            .insert(header::AUTHORIZATION, "Bearer client-token".parse()?);
+
+        Ok(req)
+    }
+
+    #[tokio::test]
+    async fn on_request() -> Result<()> {
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let store = StaticSecrets::new()
+            .secret("billing-password", "s3cret")
+            .secret("search-token", "t0ken")
+            .secret("maps-key", "k3y");
+        let inject = Inject::new(Arc::new(store))
+            .rule(
+                Matcher::Host("billing.internal".to_string()),
+                Injection::Basic {
+                    username: "svc".to_string(),
+                    password: "billing-password".to_string(),
+                },
+            )
+            .rule(
+                Matcher::Host("*.search.internal".to_string()),
+                Injection::Bearer {
+                    token: "search-token".to_string(),
+                },
+            )
+            .rule(
+                Matcher::Host("maps.example.com".to_string()),
+                Injection::Header {
+                    name: HeaderName::from_static("x-api-key"),
+                    value: "maps-key".to_string(),
+                },
+            )
+            .rule(
+                Matcher::Host("broken.internal".to_string()),
+                Injection::Bearer {
+                    token: "missing".to_string(),
+                },
+            );
+
+        let injected = |forward| match forward {
+            Forward::Modify(req) => req.headers,
+            _ => panic!("request not modified"),
+        };
+
+        let headers = injected(
+            inject
+                .on_request(&flow, request("http://billing.internal/")?)
+                .await,
+        );
+        assert_eq!(headers[header::AUTHORIZATION], "Basic c3ZjOnMzY3JldA=="); // svc:s3cret
+        assert!(headers[header::AUTHORIZATION].is_sensitive());
+
+        let headers = injected(
+            inject
+                .on_request(&flow, request("http://eu.search.internal/")?)
+                .await,
+        );
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer t0ken");
+
+        let headers = injected(
+            inject
+                .on_request(&flow, request("http://maps.example.com/")?)
+                .await,
+        );
+        assert_eq!(headers["x-api-key"], "k3y");
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer client-token");
+
+        assert!(matches!(
+            inject
+                .on_request(&flow, request("http://example.com/")?)
+                .await,
+            Forward::DoNothing
+        ));
+        match inject
+            .on_request(&flow, request("http://broken.internal/")?)
+            .await
+        {
+            Forward::Reply(resp) => assert_eq!(resp.status, StatusCode::BAD_GATEWAY),
+            _ => panic!("request not rejected"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn env_secrets() -> Result<()> {
+        std::env::set_var("KKOWA_TEST_SECRET_token", "value");
+        let store = EnvSecrets::new("KKOWA_TEST_SECRET_");
+
+        assert_eq!(
+            store.get("token").await?.as_deref().map(String::as_str),
+            Some("value")
+        );
+        assert!(store.get("missing").await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_secrets() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kkowa-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("token"), "value\n")?;
+        let store = FileSecrets::new(&dir);
+
+        assert_eq!(
+            store.get("token").await?.as_deref().map(String::as_str),
+            Some("value")
+        );
+        assert!(store.get("missing").await?.is_none());
+        assert!(store.get("../token").await?.is_none());
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
@@ -1,7 +1,16 @@
 pub mod auth;
+pub mod authz;
+pub mod cache;
+pub mod fault;
 pub mod http;
+pub mod inject;
 pub mod metrics;
+pub mod mirror;
 pub mod proxy;
+pub mod ratelimit;
+pub mod replay;
+pub mod sigv4;
+pub mod vcr;
 pub mod web;
 
 pub use proxy::Proxy;
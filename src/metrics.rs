@@ -1,9 +1,42 @@
 use lazy_static::lazy_static;
-use metrics::{register_counter, register_histogram, Counter, Histogram};
+use metrics::{increment_counter, register_counter, register_histogram, Counter, Histogram};
+
+use crate::http::StatusCode;
 
 // Prometheus metrics; check args in `opts!` for detail
 lazy_static! {
     pub static ref HTTP_REQ_COUNTER: Counter = register_counter!("http_requests_total");
     pub static ref HTTP_REQ_HISTOGRAM: Histogram =
         register_histogram!("http_request_duration_seconds");
+    pub static ref COALESCED_REQ_COUNTER: Counter = register_counter!("coalesced_requests_total");
+    pub static ref AUTH_CACHE_HIT_COUNTER: Counter = register_counter!("auth_cache_hits_total");
+    pub static ref AUTH_CACHE_MISS_COUNTER: Counter = register_counter!("auth_cache_misses_total");
+    pub static ref AUTH_FAILURE_COUNTER: Counter = register_counter!("auth_failures_total");
+    pub static ref AUTH_LOCKOUT_COUNTER: Counter = register_counter!("auth_lockouts_total");
+    pub static ref AUTH_LOCKED_OUT_COUNTER: Counter =
+        register_counter!("auth_locked_out_requests_total");
+    pub static ref AUTHZ_DENIED_COUNTER: Counter = register_counter!("authz_denied_requests_total");
+    pub static ref RATE_LIMITED_COUNTER: Counter = register_counter!("rate_limited_requests_total");
+    pub static ref MIRROR_REQ_COUNTER: Counter = register_counter!("mirror_requests_total");
+    pub static ref MIRROR_SKIP_COUNTER: Counter =
+        register_counter!("mirror_skipped_requests_total");
+    pub static ref MIRROR_ERR_COUNTER: Counter = register_counter!("mirror_errors_total");
+    pub static ref MIRROR_MISMATCH_COUNTER: Counter = register_counter!("mirror_mismatches_total");
+    pub static ref MIRROR_REQ_HISTOGRAM: Histogram =
+        register_histogram!("mirror_request_duration_seconds");
+}
+
+/// Count proxied requests by authenticated user.
+pub fn user_request(user: &str) {
+    increment_counter!("http_requests_by_user_total", "user" => user.to_owned());
+}
+
+/// Count shadow responses by status code.
+pub fn mirror_response(status: StatusCode) {
+    increment_counter!("mirror_responses_total", "status" => status.as_str().to_owned());
+}
+
+/// Count injected faults by kind.
+pub fn fault_injected(fault: &str) {
+    increment_counter!("faults_injected_total", "fault" => fault.to_owned());
 }
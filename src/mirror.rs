@@ -0,0 +1,384 @@
+//! Traffic mirroring (shadowing) to secondary upstream.
+
+use std::{collections::HashMap,
+          sync::{atomic::{AtomicU64, Ordering},
+                 Arc},
+          time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use http::uri::Authority;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tracing::{debug, warn};
+
+use crate::{http::{header, remove_hop_by_hop_headers, Diff, HeaderName, Request, Response},
+            metrics,
+            proxy::{Client, Flow, Forward, Handler, Matcher, Reverse}};
+
+/// Headers carrying client credentials, stripped from shadow requests by default.
+const CREDENTIAL_HEADERS: &[HeaderName] = &[header::AUTHORIZATION, header::COOKIE];
+
+/// How long shadow request waits for primary response to compare with.
+const PRIMARY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Source of mirror IDs, telling apart shadow requests of multiple mirrors for same request.
+static MIRROR_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Shadow requests waiting for primary response of current request, by mirror ID. Kept in flow's request state, so
+/// that waiting shadow requests give up as soon as request is answered without primary response, such as when later
+/// handler replied.
+#[derive(Debug, Default)]
+struct Pending(HashMap<u64, oneshot::Sender<Response>>);
+
+/// Handler duplicating selected requests to shadow upstream, in background.
+///
+/// Shadow request is sent as soon as request passes handler, concurrently with primary one. Client is always served
+/// from primary upstream; shadow responses are discarded after their status, latency and difference from primary
+/// response are recorded in metrics. Requests beyond concurrency limit are not mirrored.
+#[derive(Debug)]
+pub struct Mirror {
+    upstream: Authority,
+    matcher: Matcher,
+
+    /// Headers excluded from response comparison.
+    ignore_headers: Vec<HeaderName>,
+
+    /// Whether to send `Authorization` and `Cookie` headers to shadow upstream.
+    forward_credentials: bool,
+
+    /// Permits for in-flight shadow requests.
+    permits: Arc<Semaphore>,
+
+    /// Receives differences of shadow responses, if set.
+    diffs: Option<mpsc::Sender<Diff>>,
+
+    id: u64,
+}
+
+impl Mirror {
+    /// Create new mirror with up to 64 concurrent shadow requests.
+    pub fn new(upstream: Authority, matcher: Matcher) -> Self {
+        Self {
+            upstream,
+            matcher,
+            ignore_headers: vec![header::DATE],
+            forward_credentials: false,
+            permits: Arc::new(Semaphore::new(64)),
+            diffs: None,
+            id: MIRROR_SEQ.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Set headers excluded from response comparison, replacing defaults (`Date`).
+    pub fn ignore_headers(mut self, headers: Vec<HeaderName>) -> Self {
+        self.ignore_headers = headers;
+        self
+    }
+
+    /// Send client credentials to shadow upstream too. Only enable for upstreams as trusted as primary one.
+    pub fn forward_credentials(mut self, forward: bool) -> Self {
+        self.forward_credentials = forward;
+        self
+    }
+
+    /// Maximum number of shadow requests in flight.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(limit));
+        self
+    }
+
+    /// Report difference of each compared shadow response to channel, dropping reports while channel is full.
+    pub fn diffs(mut self, sender: mpsc::Sender<Diff>) -> Self {
+        self.diffs = Some(sender);
+        self
+    }
+}
+
+#[async_trait]
+impl Handler for Mirror {
+    async fn on_request(&self, flow: &Flow, req: Request) -> Forward {
+        if !self.matcher.matches(flow, &req) {
+            return Forward::DoNothing;
+        }
+        let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() else {
+            debug!("too many shadow requests in flight, not mirroring {uri}", uri = req.uri);
+            metrics::MIRROR_SKIP_COUNTER.increment(1);
+            return Forward::DoNothing;
+        };
+
+        let mut shadow_req = req;
+        remove_hop_by_hop_headers(&mut shadow_req.headers);
+        if !self.forward_credentials {
+            for name in CREDENTIAL_HEADERS {
+                shadow_req.headers.remove(name);
+            }
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut extensions = flow.extensions().lock().unwrap();
+            match extensions.get_mut::<Pending>() {
+                Some(pending) => {
+                    pending.0.insert(self.id, sender);
+                }
+                None => {
+                    extensions.insert(Pending(HashMap::from([(self.id, sender)])));
+                }
+            }
+        }
+
+        let client = flow.app().client().clone();
+        let upstream = self.upstream.clone();
+        let ignore_headers = self.ignore_headers.clone();
+        let diffs = self.diffs.clone();
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            match shadow(&client, &upstream, shadow_req, receiver, &ignore_headers).await {
+                Ok(Some(diff)) => {
+                    if let Some(diffs) = diffs {
+                        let _ = diffs.try_send(diff);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => warn!("shadow request to {upstream} failed: {err}"),
+            }
+        });
+
+        Forward::DoNothing
+    }
+
+    async fn on_response(&self, flow: &Flow, resp: Response) -> Reverse {
+        let primary = flow
+            .extensions()
+            .lock()
+            .unwrap()
+            .get_mut::<Pending>()
+            .and_then(|pending| pending.0.remove(&self.id));
+        if let Some(primary) = primary {
+            let _ = primary.send(resp);
+        }
+
+        Reverse::DoNothing
+    }
+}
+
+/// Send request to shadow upstream, then compare response with primary one once it arrives and record results.
+/// Returns `None` if primary response never came, such as when other handler replied.
+async fn shadow(
+    client: &Client,
+    upstream: &Authority,
+    mut req: Request,
+    primary: oneshot::Receiver<Response>,
+    ignore_headers: &[HeaderName],
+) -> Result<Option<Diff>, Box<dyn std::error::Error + Send + Sync>> {
+    metrics::MIRROR_REQ_COUNTER.increment(1);
+
+    req.set_authority(upstream).map_err(|err| {
+        metrics::MIRROR_ERR_COUNTER.increment(1);
+        err
+    })?;
+
+    let start = Instant::now();
+    let resp = client.request(req.clone().into()).await.map_err(|err| {
+        metrics::MIRROR_ERR_COUNTER.increment(1);
+        err
+    })?;
+    let resp = Response::from(resp, req).await;
+    metrics::MIRROR_REQ_HISTOGRAM.record(start.elapsed().as_secs_f64());
+    metrics::mirror_response(resp.status);
+
+    let Ok(Ok(primary)) = tokio::time::timeout(PRIMARY_TIMEOUT, primary).await else {
+        debug!("no primary response to compare shadow response for {uri} with", uri = resp.request.uri);
+        return Ok(None);
+    };
+    let diff = Diff::between(&primary, &resp, ignore_headers);
+    if !diff.is_empty() {
+        metrics::MIRROR_MISMATCH_COUNTER.increment(1);
+    }
+    debug!(
+        "shadow response for {uri}: {summary}",
+        uri = primary.request.uri,
+        summary = diff.summary()
+    );
+
+    Ok(Some(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, str::FromStr, time::Duration};
+
+    use anyhow::Result;
+    use httpmock::prelude::*;
+    use tokio::sync::{mpsc, oneshot};
+
+    use super::Mirror;
+    use crate::{http::{header, Request, Response, StatusCode},
+                proxy::{Client, Forward, Handler, Matcher, Reverse},
+                Proxy};
+
+    fn primary(uri: &str) -> Result<Response> {
+        let request = Request::builder()
+            .uri(uri.parse()?)
+            .header(header::AUTHORIZATION, "Bearer secret".parse()?)
+            .build()?;
+
+        Ok(Response::builder()
+            .payload(b"Hello World!".to_vec())
+            .request(request)
+            .build()?)
+    }
+
+    #[tokio::test]
+    async fn shadow() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/hello-world");
+            then.status(500).body("Hello World!");
+        });
+        let primary = primary("http://example.com/hello-world")?;
+        let (sender, receiver) = oneshot::channel();
+        sender.send(primary.clone()).unwrap();
+
+        let diff = super::shadow(
+            &Client::default(),
+            &server.address().to_string().parse()?,
+            primary.request,
+            receiver,
+            &[],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        mock.assert();
+        assert_eq!(
+            diff.status,
+            Some((StatusCode::OK, StatusCode::INTERNAL_SERVER_ERROR))
+        );
+        assert!(diff.body.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mirrored() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/hello-world").matches(|req| {
+                req.headers.as_ref().map_or(true, |headers| {
+                    !headers
+                        .iter()
+                        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+                })
+            });
+            then.status(200).body("Good Evening");
+        });
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let (sender, mut diffs) = mpsc::channel(1);
+        let mirror = Mirror::new(server.address().to_string().parse()?, Matcher::Any).diffs(sender);
+        let primary = primary("http://example.com/hello-world")?;
+
+        // Shadow request starts before primary response arrives
+        assert!(matches!(
+            mirror.on_request(&flow, primary.request.clone()).await,
+            Forward::DoNothing
+        ));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while mock.hits() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+
+        assert!(matches!(
+            mirror.on_response(&flow, primary).await,
+            Reverse::DoNothing
+        ));
+        let diff = tokio::time::timeout(Duration::from_secs(5), diffs.recv())
+            .await?
+            .unwrap();
+        mock.assert();
+        assert!(diff.status.is_none());
+        assert_eq!(diff.body.unwrap().offset, 0);
+        assert!(flow
+            .extensions()
+            .lock()
+            .unwrap()
+            .get::<super::Pending>()
+            .map_or(true, |pending| pending.0.is_empty()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unanswered() -> Result<()> {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.any_request();
+            then.status(200);
+        });
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let mirror =
+            Mirror::new(server.address().to_string().parse()?, Matcher::Any).concurrency(1);
+        let primary = primary("http://example.com/hello-world")?;
+
+        // Request answered without primary response releases shadow request right away
+        mirror.on_request(&flow, primary.request.clone()).await;
+        assert_eq!(mirror.permits.available_permits(), 0);
+        drop(flow);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while mirror.permits.available_permits() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unmatched_not_mirrored() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.any_request();
+            then.status(200);
+        });
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let mirror = Mirror::new(
+            server.address().to_string().parse()?,
+            Matcher::Host("*.example.com".to_string()),
+        );
+        let primary = primary("http://example.org/hello-world")?;
+
+        let action = mirror.on_request(&flow, primary.request.clone()).await;
+        assert!(matches!(action, Forward::DoNothing));
+        let action = mirror.on_response(&flow, primary).await;
+        tokio::task::yield_now().await;
+
+        assert!(matches!(action, Reverse::DoNothing));
+        assert_eq!(mock.hits(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrency() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.any_request();
+            then.status(200).delay(Duration::from_millis(200));
+        });
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let mirror =
+            Mirror::new(server.address().to_string().parse()?, Matcher::Any).concurrency(1);
+        let primary = primary("http://example.com/hello-world")?;
+
+        mirror.on_request(&flow, primary.request.clone()).await;
+        mirror.on_request(&flow, primary.request.clone()).await;
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        assert_eq!(mock.hits(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! Collapsing of concurrent identical requests into single upstream request.
+
+use std::{collections::HashMap,
+          sync::{atomic::{AtomicU64, Ordering},
+                 Arc, Mutex}};
+
+use hyper::body::Bytes;
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use super::Client;
+use crate::{cache::CacheControl,
+            http::{header, HeaderName, Headers, Method, Request, Response, StatusCode, Version},
+            metrics};
+
+/// Request headers which may change response, thus part of coalescing key.
+const KEY_HEADERS: &[HeaderName] = &[
+    header::ACCEPT,
+    header::ACCEPT_ENCODING,
+    header::ACCEPT_LANGUAGE,
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::RANGE,
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("upstream request failed: {0}")]
+    Request(#[from] hyper::Error),
+
+    /// Request of leader, which current request waited for, failed.
+    #[error("coalesced upstream request failed: {0}")]
+    Leader(String),
+}
+
+/// Upstream response buffered once and shared among all waiters.
+#[derive(Debug)]
+pub struct Shared {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Bytes,
+}
+
+impl Shared {
+    /// Build response for client, sharing buffered body without copy.
+    pub fn to_hyper(&self) -> hyper::Response<hyper::Body> {
+        let mut builder = hyper::Response::builder()
+            .status(self.status)
+            .version(self.version);
+        *(builder.headers_mut().unwrap()) = self.headers.clone();
+
+        builder.body(self.body.clone().into()).unwrap()
+    }
+
+    /// Whether response may be handed to other clients than one it was requested for.
+    pub fn is_shareable(&self) -> bool {
+        let cc = CacheControl::parse(&self.headers);
+
+        !(cc.private || cc.no_store || self.headers.contains_key(header::SET_COOKIE))
+    }
+
+    /// Build crate response for handlers, copying body.
+    pub fn to_response(&self, request: Request) -> Response {
+        Response::new(
+            self.status,
+            self.version,
+            self.headers.clone(),
+            self.body.to_vec(),
+            request,
+        )
+    }
+}
+
+type Outcome = Result<Arc<Shared>, String>;
+
+/// Collapses concurrent `GET` and `HEAD` requests for same resource into one upstream request, fanning out the
+/// response to all waiters. Waiters re-issue own request if shared response turns out private to leader, and take
+/// over if leader is cancelled.
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    inflight: Mutex<HashMap<String, broadcast::Sender<Outcome>>>,
+    collapsed: AtomicU64,
+}
+
+/// Removes in-flight entry when leader finishes or is cancelled, so waiters never hang.
+struct Inflight<'a> {
+    coalescer: &'a Coalescer,
+    key: String,
+    finished: bool,
+}
+
+impl Inflight<'_> {
+    /// Remove in-flight entry and notify waiters with outcome.
+    fn finish(mut self, outcome: Outcome) {
+        if let Some(tx) = self.coalescer.inflight.lock().unwrap().remove(&self.key) {
+            let _ = tx.send(outcome);
+        }
+        self.finished = true;
+    }
+}
+
+impl Drop for Inflight<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.coalescer.inflight.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+impl Coalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of requests served by waiting on other identical request, rather than requesting upstream.
+    pub fn collapsed(&self) -> u64 {
+        self.collapsed.load(Ordering::Relaxed)
+    }
+
+    /// Key identifying identical requests, or `None` if request must not be coalesced, including when client asks
+    /// for fresh response by `Cache-Control: no-store` or `no-cache`.
+    pub fn key(req: &Request) -> Option<String> {
+        if !(req.method == Method::GET || req.method == Method::HEAD) || !req.payload.is_empty() {
+            return None;
+        }
+        let cc = CacheControl::parse(&req.headers);
+        if cc.no_store || cc.no_cache {
+            return None;
+        }
+
+        let mut key = format!("{method} {uri}", method = req.method, uri = req.uri);
+        for name in KEY_HEADERS {
+            for value in req.headers.get_all(name) {
+                key.push_str(&format!("\n{name}: {value:?}"));
+            }
+        }
+
+        Some(key)
+    }
+
+    /// Send request to upstream, or wait for in-flight identical request to finish.
+    pub async fn request(
+        &self,
+        client: &Client,
+        key: String,
+        req: Request,
+    ) -> Result<Arc<Shared>, Error> {
+        loop {
+            let rx = {
+                let mut inflight = self.inflight.lock().unwrap();
+                match inflight.get(&key) {
+                    Some(tx) => Some(tx.subscribe()),
+                    None => {
+                        inflight.insert(key.clone(), broadcast::channel(1).0);
+                        None
+                    }
+                }
+            };
+            let Some(mut rx) = rx else { break };
+
+            debug!("waiting for in-flight request {key}");
+            match rx.recv().await {
+                Ok(Ok(shared)) if shared.is_shareable() => {
+                    self.collapsed.fetch_add(1, Ordering::Relaxed);
+                    metrics::COALESCED_REQ_COUNTER.increment(1);
+                    return Ok(shared);
+                }
+                Ok(Ok(_)) => {
+                    debug!("response for {key} is private to leader, requesting own");
+                    return Ok(Self::fetch(client, req).await?);
+                }
+                Ok(Err(err)) => return Err(Error::Leader(err)),
+                // Leader cancelled; take over or wait for whoever did
+                Err(_) => debug!("leader of {key} cancelled"),
+            }
+        }
+
+        // Lead request; guard cleans up in-flight entry even if cancelled
+        let guard = Inflight {
+            coalescer: self,
+            key,
+            finished: false,
+        };
+        let result = Self::fetch(client, req).await;
+        guard.finish(match &result {
+            Ok(shared) => Ok(Arc::clone(shared)),
+            Err(err) => Err(err.to_string()),
+        });
+
+        result.map_err(Error::from)
+    }
+
+    async fn fetch(client: &Client, req: Request) -> Result<Arc<Shared>, hyper::Error> {
+        let resp = client.request(req.into()).await?;
+        let (parts, body) = resp.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+
+        Ok(Arc::new(Shared {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use anyhow::Result;
+    use httpmock::prelude::*;
+
+    use super::Coalescer;
+    use crate::{http::{header, Method, Request},
+                proxy::Client};
+
+    #[test]
+    fn key() -> Result<()> {
+        let req = Request::builder()
+            .uri("http://example.com/artifact".parse()?)
+            .header(header::ACCEPT_ENCODING, "gzip".parse()?)
+            .header(header::USER_AGENT, "curl/7.86.0".parse()?)
+            .build()?;
+        assert_eq!(
+            Coalescer::key(&req),
+            Some("GET http://example.com/artifact\naccept-encoding: \"gzip\"".to_string())
+        );
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/artifact".parse()?)
+            .build()?;
+        assert_eq!(Coalescer::key(&req), None);
+
+        let req = Request::builder()
+            .uri("http://example.com/artifact".parse()?)
+            .header(header::CACHE_CONTROL, "no-cache".parse()?)
+            .build()?;
+        assert_eq!(Coalescer::key(&req), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/artifact");
+            then.status(200)
+                .delay(Duration::from_millis(200))
+                .body("Hello World!");
+        });
+        let coalescer = Arc::new(Coalescer::new());
+        let req = Request::builder()
+            .uri(server.url("/artifact").parse()?)
+            .build()?;
+        let key = Coalescer::key(&req).unwrap();
+
+        let handles: Vec<_> = (0..5)
+            .map(|_| {
+                let (coalescer, key, req) = (Arc::clone(&coalescer), key.clone(), req.clone());
+                tokio::task::spawn(
+                    async move { coalescer.request(&Client::default(), key, req).await },
+                )
+            })
+            .collect();
+        for handle in handles {
+            let shared = handle.await?.unwrap();
+            assert_eq!(shared.body, "Hello World!");
+        }
+
+        mock.assert_hits(1);
+        assert_eq!(coalescer.collapsed(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_private() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/session");
+            then.status(200)
+                .header("Set-Cookie", "session=secret")
+                .delay(Duration::from_millis(200))
+                .body("Hello World!");
+        });
+        let coalescer = Arc::new(Coalescer::new());
+        let req = Request::builder()
+            .uri(server.url("/session").parse()?)
+            .build()?;
+        let key = Coalescer::key(&req).unwrap();
+
+        // Waiters re-issue own request rather than receiving leader's cookie
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let (coalescer, key, req) = (Arc::clone(&coalescer), key.clone(), req.clone());
+                tokio::task::spawn(
+                    async move { coalescer.request(&Client::default(), key, req).await },
+                )
+            })
+            .collect();
+        for handle in handles {
+            assert!(!handle.await?.unwrap().is_shareable());
+        }
+
+        mock.assert_hits(3);
+        assert_eq!(coalescer.collapsed(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_leader_cancelled() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/artifact");
+            then.status(200)
+                .delay(Duration::from_millis(200))
+                .body("Hello World!");
+        });
+        let coalescer = Arc::new(Coalescer::new());
+        let req = Request::builder()
+            .uri(server.url("/artifact").parse()?)
+            .build()?;
+        let key = Coalescer::key(&req).unwrap();
+
+        let spawn = || {
+            let (coalescer, key, req) = (Arc::clone(&coalescer), key.clone(), req.clone());
+            tokio::task::spawn(async move { coalescer.request(&Client::default(), key, req).await })
+        };
+        let leader = spawn();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let waiter = spawn();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Waiter takes over once leader's client goes away
+        leader.abort();
+        assert_eq!(waiter.await?.unwrap().body, "Hello World!");
+        mock.assert_hits(2);
+
+        Ok(())
+    }
+}
@@ -1,12 +1,26 @@
 //! Context module for handlers.
 
 use std::{net::SocketAddr,
-          sync::{atomic::Ordering, Arc}};
+          sync::{atomic::Ordering, Arc, Mutex}};
 
 use getset::{Getters, MutGetters};
+use http::Extensions;
 
-use super::Proxy;
-use crate::auth::Credentials;
+use super::{Pipeline, Proxy};
+use crate::auth::{Credentials, Principal};
+
+/// Details of TLS session negotiated with client.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// Server name requested by client via SNI.
+    pub server_name: Option<String>,
+
+    /// Application protocol negotiated via ALPN.
+    pub alpn_protocol: Option<Vec<u8>>,
+
+    /// DER-encoded certificate chain presented by client, end-entity certificate first.
+    pub peer_certificates: Vec<Vec<u8>>,
+}
 
 /// Shared state of application context across handlers.
 #[derive(Clone, Debug, Getters, MutGetters)]
@@ -18,13 +32,30 @@ pub struct Flow {
     /// Parent app which current context have derive from.
     app: Arc<Proxy>,
 
+    /// Authenticators and handlers of app for current request, loaded as request arrives and kept even if app's
+    /// pipeline replaced meanwhile.
+    #[getset(get = "pub")]
+    pipeline: Arc<Pipeline>,
+
     /// Incoming request source address.
     #[getset(get = "pub")]
     client: SocketAddr,
 
+    /// TLS session details, if client connected over TLS.
+    #[getset(get = "pub", get_mut = "pub")]
+    tls: Option<TlsInfo>,
+
     /// Proxy authentication credentials. First passed auth credentials will be set if multiple auth backends set.
     #[getset(get = "pub", get_mut = "pub")]
     auth: Option<Credentials>,
+
+    /// Identity of authenticated proxy user, returned by authenticator which accepted `auth` credentials.
+    #[getset(get = "pub", get_mut = "pub")]
+    principal: Option<Principal>,
+
+    /// State handlers keep for current request, dropped once request is answered.
+    #[getset(get = "pub")]
+    extensions: Arc<Mutex<Extensions>>,
 }
 
 impl Flow {
@@ -32,15 +63,25 @@ impl Flow {
     pub fn new(proxy: Proxy, client: SocketAddr) -> Self {
         Self {
             id: proxy.counter.fetch_add(1, Ordering::SeqCst),
+            pipeline: proxy.pipeline(),
             app: Arc::new(proxy),
             client,
+            tls: None,
             auth: None,
+            principal: None,
+            extensions: Arc::default(),
         }
     }
 
     pub fn app(&self) -> Arc<Proxy> {
         Arc::clone(&self.app)
     }
+
+    /// Prepare for request about to be served; take app's current pipeline and start with fresh request state.
+    pub(crate) fn start_request(&mut self) {
+        self.pipeline = self.app.pipeline();
+        self.extensions = Arc::default();
+    }
 }
 
 #[cfg(test)]
@@ -1,10 +1,10 @@
 //! Module for base handler constraint.
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 
-use super::Flow;
-use crate::http::{Request, Response};
+use super::{Conditions, Flow};
+use crate::http::{Request, Response, Uri};
 
 /// Enum for handler actions on forward direction (a request, from client to proxy).
 pub enum Forward {
@@ -14,8 +14,23 @@ pub enum Forward {
     /// Make changes on request and pass to next handler.
     Modify(Box<Request>),
 
+    /// Pause for given duration, then pass request unchanged to next handler.
+    Delay(Duration),
+
+    /// Send request to upstream at given URI, of which only scheme and authority are used, instead of the one in
+    /// request URI. Original `Host` header is kept, or set to original authority if absent. Remaining handlers still
+    /// run and see original URI; last reroute wins.
+    Reroute(Uri),
+
+    /// Send given request to upstream right away, skipping remaining handlers on request. Response handlers still
+    /// run.
+    Send(Box<Request>),
+
     /// Early return response without making requests to remote destination, skipping all remaining handlers.
     Reply(Box<Response>),
+
+    /// Close client connection without any response, skipping all remaining handlers.
+    Abort,
 }
 
 /// Enum for handler actions on reverse direction (a response, from proxy to client).
@@ -26,8 +41,22 @@ pub enum Reverse {
     /// Make changes on response and pass to next handler.
     Modify(Box<Response>),
 
+    /// Pause for given duration, then pass response unchanged to next handler.
+    Delay(Duration),
+
     /// Return given response to client, skipping all remaining handlers.
     Replace(Box<Response>),
+
+    /// Return given response to client with body paced under given network conditions, skipping all remaining
+    /// handlers.
+    Throttle(Box<Response>, Conditions),
+
+    /// Return given response to client, but close connection after given number of body bytes, skipping all remaining
+    /// handlers. Headers, including `Content-Length`, are sent as is.
+    Truncate(Box<Response>, usize),
+
+    /// Close client connection without sending response, skipping all remaining handlers.
+    Abort,
 }
 
 /// Basic handler trait.
@@ -42,6 +71,21 @@ async fn on_response(&self, _flow: &Flow, _resp: Response) -> Reverse {
     }
 }
 
+/// Shared handler, so that its owner can still reach it once pipeline took it, such as to flush recorded state.
+#[async_trait]
+impl<H> Handler for Arc<H>
+where
+    H: Handler + Send + ?Sized,
+{
+    async fn on_request(&self, flow: &Flow, req: Request) -> Forward {
+        (**self).on_request(flow, req).await
+    }
+
+    async fn on_response(&self, flow: &Flow, resp: Response) -> Reverse {
+        (**self).on_response(flow, resp).await
+    }
+}
+
 /// Simple handler that does nothing.
 #[derive(Debug)]
 pub struct Dummy;
@@ -0,0 +1,144 @@
+//! Request matchers to select flows which built-in handlers apply to.
+
+use std::net::IpAddr;
+
+use super::Flow;
+use crate::http::{HeaderName, Method, Request};
+
+/// Predicate over flow and request.
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    /// Matches every request.
+    Any,
+
+    /// Client address equals to given one.
+    Client(IpAddr),
+
+    /// Request method equals to given one.
+    Method(Method),
+
+    /// Request host matches given pattern. Pattern may contain `*` wildcards, such as `*.example.com`.
+    Host(String),
+
+    /// Request path starts with given prefix.
+    PathPrefix(String),
+
+    /// Request has given header, with given value if set.
+    Header(HeaderName, Option<String>),
+
+    /// Negation of inner matcher.
+    Not(Box<Matcher>),
+
+    /// All inner matchers match.
+    All(Vec<Matcher>),
+
+    /// At least one of inner matchers match.
+    AnyOf(Vec<Matcher>),
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Self::Any
+    }
+}
+
+impl Matcher {
+    pub fn matches(&self, flow: &Flow, req: &Request) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Client(addr) => flow.client().ip() == *addr,
+            Self::Method(method) => req.method == *method,
+            Self::Host(pattern) => req.uri.host().map_or(false, |host| {
+                glob(&pattern.to_lowercase(), &host.to_lowercase())
+            }),
+            Self::PathPrefix(prefix) => req.uri.path().starts_with(prefix),
+            Self::Header(name, value) => match value {
+                Some(value) => req
+                    .headers
+                    .get_all(name)
+                    .iter()
+                    .any(|v| v.as_bytes() == value.as_bytes()),
+                None => req.headers.contains_key(name),
+            },
+            Self::Not(inner) => !inner.matches(flow, req),
+            Self::All(inner) => inner.iter().all(|m| m.matches(flow, req)),
+            Self::AnyOf(inner) => inner.iter().any(|m| m.matches(flow, req)),
+        }
+    }
+}
+
+/// Match text against pattern with `*` wildcards, each matching any sequence of characters.
+pub(crate) fn glob(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == t[ti] {
+            pi += 1;
+            ti += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            pi = bp + 1;
+            ti = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, str::FromStr};
+
+    use anyhow::Result;
+    use rstest::rstest;
+
+    use super::Matcher;
+    use crate::{http::{header, Method, Request},
+                Proxy};
+
+    #[rstest]
+    #[case("example.com", "example.com", true)]
+    #[case("*.example.com", "api.example.com", true)]
+    #[case("*.example.com", "example.com", false)]
+    #[case("api.*.com", "api.example.com", true)]
+    #[case("*", "anything", true)]
+    #[case("example.*", "example.org.evil", true)]
+    #[case("example.com", "example.org", false)]
+    fn glob(#[case] pattern: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_eq!(super::glob(pattern, text), expected);
+    }
+
+    #[test]
+    fn matches() -> Result<()> {
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://API.example.com/v1/items".parse()?)
+            .header(header::CONTENT_TYPE, "application/json".parse()?)
+            .build()?;
+
+        assert!(Matcher::Any.matches(&flow, &req));
+        assert!(Matcher::Client("127.0.0.1".parse()?).matches(&flow, &req));
+        assert!(Matcher::Host("*.example.com".to_string()).matches(&flow, &req));
+        assert!(Matcher::All(vec![
+            Matcher::Method(Method::POST),
+            Matcher::PathPrefix("/v1/".to_string()),
+            Matcher::Header(header::CONTENT_TYPE, Some("application/json".to_string())),
+        ])
+        .matches(&flow, &req));
+        assert!(!Matcher::AnyOf(vec![
+            Matcher::Method(Method::GET),
+            Matcher::Not(Box::new(Matcher::Header(header::CONTENT_TYPE, None))),
+        ])
+        .matches(&flow, &req));
+
+        Ok(())
+    }
+}
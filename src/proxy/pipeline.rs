@@ -0,0 +1,71 @@
+//! Swappable set of authenticators and handlers requests run through.
+
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+
+use super::{Error, Handler};
+use crate::auth::Authenticator;
+
+/// Authenticators and handlers applied to requests, fixed for each request once it arrives.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    /// Number of times pipeline has been replaced since proxy created.
+    generation: u64,
+
+    auths: Arc<Vec<Box<dyn Authenticator + Send + Sync>>>,
+    handlers: Arc<Vec<Box<dyn Handler + Send + Sync>>>,
+}
+
+impl Pipeline {
+    pub fn new(
+        auths: Vec<Box<dyn Authenticator + Send + Sync>>,
+        handlers: Vec<Box<dyn Handler + Send + Sync>>,
+    ) -> Self {
+        Self {
+            generation: 0,
+            auths: Arc::new(auths),
+            handlers: Arc::new(handlers),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn auths(&self) -> &[Box<dyn Authenticator + Send + Sync>] {
+        &self.auths
+    }
+
+    pub fn handlers(&self) -> &[Box<dyn Handler + Send + Sync>] {
+        &self.handlers
+    }
+
+    /// Same pipeline with given authenticators.
+    pub(crate) fn with_auths(&self, auths: Arc<Vec<Box<dyn Authenticator + Send + Sync>>>) -> Self {
+        Self {
+            auths,
+            ..self.clone()
+        }
+    }
+
+    /// Same pipeline with given handlers.
+    pub(crate) fn with_handlers(&self, handlers: Arc<Vec<Box<dyn Handler + Send + Sync>>>) -> Self {
+        Self {
+            handlers,
+            ..self.clone()
+        }
+    }
+
+    /// Pipeline succeeding given one.
+    pub(crate) fn succeed(mut self, previous: &Self) -> Self {
+        self.generation = previous.generation + 1;
+        self
+    }
+}
+
+/// Source of pipelines to reload proxy with, such as one building handlers from configuration file.
+#[async_trait]
+pub trait PipelineSource: Debug + Send + Sync {
+    async fn load(&self) -> Result<Pipeline, Error>;
+}
@@ -0,0 +1,340 @@
+//! Bandwidth throttling and network condition emulation, such as for testing apps over mobile networks.
+
+use std::{str::FromStr,
+          time::{Duration, Instant}};
+
+use hyper::body::{Body, Bytes};
+use rand::Rng;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::trace;
+
+use crate::http::{Payload, Response};
+
+/// Largest chunk written at once, regardless of bandwidth.
+const MAX_CHUNK: usize = 16 * 1024;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("unknown network preset: {0}")]
+    UnknownPreset(String),
+}
+
+/// Common network profiles, following those of browser developer tools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Gprs,
+    Regular2G,
+    Good2G,
+    Regular3G,
+    Good3G,
+    Regular4G,
+    Dsl,
+    Wifi,
+}
+
+impl FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gprs" => Ok(Self::Gprs),
+            "regular-2g" => Ok(Self::Regular2G),
+            "good-2g" => Ok(Self::Good2G),
+            "regular-3g" => Ok(Self::Regular3G),
+            "good-3g" => Ok(Self::Good3G),
+            "regular-4g" => Ok(Self::Regular4G),
+            "dsl" => Ok(Self::Dsl),
+            "wifi" => Ok(Self::Wifi),
+            _ => Err(Error::UnknownPreset(s.to_string())),
+        }
+    }
+}
+
+impl From<Preset> for Conditions {
+    fn from(preset: Preset) -> Self {
+        // Downstream and upstream in kbit/s, and latency in milliseconds
+        let (down, up, latency) = match preset {
+            Preset::Gprs => (50, 20, 500),
+            Preset::Regular2G => (250, 50, 300),
+            Preset::Good2G => (450, 150, 150),
+            Preset::Regular3G => (750, 250, 100),
+            Preset::Good3G => (1_500, 750, 40),
+            Preset::Regular4G => (4_000, 3_000, 20),
+            Preset::Dsl => (2_000, 1_000, 5),
+            Preset::Wifi => (30_000, 15_000, 2),
+        };
+        let kbps = |rate: u64| rate * 1000 / 8;
+
+        Self::new()
+            .downstream(kbps(down))
+            .upstream(kbps(up))
+            .latency(Duration::from_millis(latency))
+    }
+}
+
+/// Emulated network conditions between client and proxy. Unlimited, with no added delay, by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Conditions {
+    /// Bandwidth to client in bytes per second.
+    downstream: Option<u64>,
+
+    /// Bandwidth from client in bytes per second.
+    upstream: Option<u64>,
+
+    /// One-way delay added before each exchange.
+    latency: Duration,
+
+    /// Maximum random delay added on top of latency.
+    jitter: Duration,
+
+    /// Probability of each chunk stalling, resembling packet loss and retransmission.
+    stall_probability: f64,
+    stall: Duration,
+}
+
+impl Conditions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit bandwidth to client, in bytes per second.
+    pub fn downstream(mut self, rate: u64) -> Self {
+        self.downstream = Some(rate.max(1));
+        self
+    }
+
+    /// Limit bandwidth from client, in bytes per second.
+    pub fn upstream(mut self, rate: u64) -> Self {
+        self.upstream = Some(rate.max(1));
+        self
+    }
+
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Stall each chunk for given duration with given probability, clamped to `[0, 1]`.
+    pub fn stalls(mut self, probability: f64, stall: Duration) -> Self {
+        self.stall_probability = probability.clamp(0.0, 1.0);
+        self.stall = stall;
+        self
+    }
+
+    /// Latency plus random jitter.
+    fn delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.latency;
+        }
+
+        self.latency + rand::thread_rng().gen_range(Duration::ZERO..=self.jitter)
+    }
+
+    /// Chunk size to pace transfer at given rate, about a tenth of second worth of bytes.
+    fn chunk_size(rate: Option<u64>) -> usize {
+        rate.map_or(MAX_CHUNK, |rate| {
+            usize::try_from(rate / 10)
+                .unwrap_or(MAX_CHUNK)
+                .clamp(1, MAX_CHUNK)
+        })
+    }
+
+    /// Time to transfer chunk of given size at given rate, including stall if any.
+    fn pace(&self, len: usize, rate: Option<u64>) -> Duration {
+        let mut pace = rate.map_or(Duration::ZERO, |rate| {
+            Duration::from_secs_f64(len as f64 / rate as f64)
+        });
+        if self.stall_probability > 0.0 && rand::thread_rng().gen_bool(self.stall_probability) {
+            trace!("stalling chunk for {stall:?}", stall = self.stall);
+            pace += self.stall;
+        }
+
+        pace
+    }
+
+    /// Wait as long as sending payload of given size from client would take.
+    pub(crate) async fn upload(&self, len: usize) {
+        let mut wait = self.delay();
+        let chunk = Self::chunk_size(self.upstream);
+        for start in (0..len).step_by(chunk) {
+            wait += self.pace(chunk.min(len - start), self.upstream);
+        }
+
+        tokio::time::sleep(wait).await;
+    }
+
+    /// Stream payload to client at downstream rate, after latency.
+    pub(crate) fn download(self, payload: Payload) -> Body {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            tokio::time::sleep(self.delay()).await;
+            let payload = Bytes::from(payload);
+            let chunk = Self::chunk_size(self.downstream);
+            for start in (0..payload.len()).step_by(chunk) {
+                let end = payload.len().min(start + chunk);
+                tokio::time::sleep(self.pace(end - start, self.downstream)).await;
+                if sender.send_data(payload.slice(start..end)).await.is_err() {
+                    trace!("client went away while streaming throttled response");
+                    break;
+                }
+            }
+        });
+
+        body
+    }
+
+    /// Convert response into hyper one, with body streamed at downstream rate.
+    pub(crate) fn response(self, mut resp: Response) -> hyper::Response<Body> {
+        let payload = std::mem::take(&mut resp.payload);
+        let mut resp: hyper::Response<Body> = resp.into();
+        *resp.body_mut() = self.download(payload);
+
+        resp
+    }
+
+    /// Copy bytes from reader to writer at given rate until end of stream. Latency is added whenever data arrives
+    /// after stream has been idle longer than latency, approximating request-response exchanges.
+    async fn pipe<R, W>(
+        self,
+        mut reader: R,
+        mut writer: W,
+        rate: Option<u64>,
+    ) -> std::io::Result<u64>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = vec![0; Self::chunk_size(rate)];
+        let mut total = 0;
+        let mut last: Option<Instant> = None;
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                writer.shutdown().await?;
+                return Ok(total);
+            }
+
+            let mut wait = self.pace(n, rate);
+            if last.map_or(true, |last| last.elapsed() > self.latency) {
+                wait += self.delay();
+            }
+            tokio::time::sleep(wait).await;
+
+            writer.write_all(&buf[..n]).await?;
+            writer.flush().await?;
+            total += n as u64;
+            last = Some(Instant::now());
+        }
+    }
+
+    /// Copy bytes between client and server in both directions under conditions, returning bytes written by client
+    /// and server respectively.
+    pub(crate) async fn tunnel<C, S>(self, client: C, server: S) -> std::io::Result<(u64, u64)>
+    where
+        C: AsyncRead + AsyncWrite,
+        S: AsyncRead + AsyncWrite,
+    {
+        let (client_read, client_write) = tokio::io::split(client);
+        let (server_read, server_write) = tokio::io::split(server);
+
+        tokio::try_join!(
+            self.pipe(client_read, server_write, self.upstream),
+            self.pipe(server_read, client_write, self.downstream)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use anyhow::Result;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{Conditions, Preset};
+    use crate::http::Response;
+
+    #[test]
+    fn preset() -> Result<()> {
+        let conditions = Conditions::from("Regular-3G".parse::<Preset>()?);
+        assert_eq!(
+            conditions,
+            Conditions::new()
+                .downstream(93_750)
+                .upstream(31_250)
+                .latency(Duration::from_millis(100))
+        );
+        assert!("5g".parse::<Preset>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn pace() {
+        let conditions = Conditions::new();
+        assert_eq!(conditions.pace(1_000, None), Duration::ZERO);
+        assert_eq!(
+            conditions.pace(1_000, Some(10_000)),
+            Duration::from_millis(100)
+        );
+
+        let conditions = conditions.stalls(1.0, Duration::from_secs(1));
+        assert_eq!(conditions.pace(1_000, None), Duration::from_secs(1));
+
+        let conditions = Conditions::new()
+            .latency(Duration::from_millis(100))
+            .jitter(Duration::from_millis(50));
+        for _ in 0..10 {
+            let delay = conditions.delay();
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(150));
+        }
+    }
+
+    #[tokio::test]
+    async fn response() -> Result<()> {
+        let conditions = Conditions::new()
+            .downstream(10_000)
+            .latency(Duration::from_millis(50));
+        let resp = Response::builder().payload(vec![0; 2_000]).build()?;
+
+        let start = Instant::now();
+        let resp = conditions.response(resp);
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        assert_eq!(body.len(), 2_000);
+        assert!(start.elapsed() >= Duration::from_millis(250));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tunnel() -> Result<()> {
+        let (mut client, client_side) = tokio::io::duplex(4096);
+        let (server_side, mut server) = tokio::io::duplex(4096);
+        let conditions = Conditions::new().upstream(10_000).downstream(5_000);
+        let tunnel = tokio::spawn(conditions.tunnel(client_side, server_side));
+
+        let start = Instant::now();
+        client.write_all(&[1; 1_000]).await?;
+        let mut buf = vec![0; 1_000];
+        server.read_exact(&mut buf).await?;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let start = Instant::now();
+        server.write_all(&[2; 1_000]).await?;
+        client.read_exact(&mut buf).await?;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(buf, vec![2; 1_000]);
+
+        client.shutdown().await?;
+        server.shutdown().await?;
+        assert_eq!(tunnel.await??, (1_000, 1_000));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+//! TLS listener support, serving proxy endpoint over HTTPS with optional client certificate verification.
+
+use std::{fmt::{self, Debug},
+          future::poll_fn,
+          io::{self, BufReader},
+          pin::Pin,
+          sync::Arc,
+          time::Duration};
+
+use hyper::server::{accept::Accept,
+                    conn::{AddrIncoming, AddrStream}};
+use rustls::{server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient,
+                      ServerConnection},
+             Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tracing::{debug, warn};
+
+use super::flow::TlsInfo;
+
+/// Time allowed for client to complete TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read PEM: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("no certificate found in PEM")]
+    NoCertificate,
+
+    #[error("no private key found in PEM")]
+    NoPrivateKey,
+
+    #[error("invalid client CA certificate: {0}")]
+    InvalidCa(String),
+
+    #[error("invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Read all certificates from PEM data.
+fn certificates(pem: &[u8]) -> Result<Vec<Certificate>, Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem))?;
+    if certs.is_empty() {
+        return Err(Error::NoCertificate);
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Read first private key, in either PKCS#8, PKCS#1 or SEC1 format, from PEM data.
+fn private_key(pem: &[u8]) -> Result<PrivateKey, Error> {
+    let mut reader = BufReader::new(pem);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => {
+                return Ok(PrivateKey(key))
+            }
+            _ => continue,
+        }
+    }
+
+    Err(Error::NoPrivateKey)
+}
+
+/// Server-side TLS settings of proxy listener.
+#[derive(Clone)]
+pub struct TlsConfig {
+    cert_chain: Vec<Certificate>,
+    key: PrivateKey,
+
+    /// Trust anchors to verify client certificates against, if client authentication enabled.
+    client_ca: Option<RootCertStore>,
+
+    /// Whether clients without certificate are rejected during handshake.
+    client_cert_required: bool,
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("cert_chain", &self.cert_chain.len())
+            .field(
+                "client_ca",
+                &self.client_ca.as_ref().map(RootCertStore::len),
+            )
+            .field("client_cert_required", &self.client_cert_required)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsConfig {
+    /// Create new config from PEM-encoded server certificate chain and private key.
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            cert_chain: certificates(cert_chain)?,
+            key: private_key(key)?,
+            client_ca: None,
+            client_cert_required: false,
+        })
+    }
+
+    /// Request client certificates, verifying them against PEM-encoded CA bundle. Clients may still connect without
+    /// certificate unless [`require_client_cert`](Self::require_client_cert) set.
+    pub fn client_ca_pem(mut self, bundle: &[u8]) -> Result<Self, Error> {
+        let mut roots = RootCertStore::empty();
+        for cert in certificates(bundle)? {
+            roots
+                .add(&cert)
+                .map_err(|err| Error::InvalidCa(err.to_string()))?;
+        }
+        self.client_ca = Some(roots);
+
+        Ok(self)
+    }
+
+    pub fn require_client_cert(mut self, required: bool) -> Self {
+        self.client_cert_required = required;
+        self
+    }
+
+    /// Build rustls server config.
+    pub fn build(self) -> Result<Arc<ServerConfig>, Error> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match self.client_ca {
+            Some(roots) if self.client_cert_required => {
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            }
+            Some(roots) => builder
+                .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots)),
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(self.cert_chain, self.key)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+impl From<&ServerConnection> for TlsInfo {
+    fn from(session: &ServerConnection) -> Self {
+        Self {
+            server_name: session.sni_hostname().map(str::to_string),
+            alpn_protocol: session.alpn_protocol().map(<[u8]>::to_vec),
+            peer_certificates: session
+                .peer_certificates()
+                .map(|certs| certs.iter().map(|cert| cert.0.clone()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Accept next TCP connection.
+async fn next(incoming: &mut AddrIncoming) -> Option<io::Result<AddrStream>> {
+    poll_fn(|cx| Pin::new(&mut *incoming).poll_accept(cx)).await
+}
+
+/// Wrap incoming connections with TLS. Handshakes run concurrently in background, so that slow clients do not hold up
+/// others; connections failing handshake are dropped.
+pub(crate) fn accept(
+    mut incoming: AddrIncoming,
+    config: Arc<ServerConfig>,
+) -> impl Accept<Conn = TlsStream<AddrStream>, Error = io::Error> {
+    let acceptor = TlsAcceptor::from(config);
+    let (tx, mut rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        loop {
+            let socket = tokio::select! {
+                // Server has shut down
+                _ = tx.closed() => break,
+                accepted = next(&mut incoming) => match accepted {
+                    Some(Ok(socket)) => socket,
+                    Some(Err(err)) => {
+                        warn!("failed to accept connection: {err}");
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+
+            let (acceptor, tx) = (acceptor.clone(), tx.clone());
+            tokio::spawn(async move {
+                let remote = socket.remote_addr();
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(stream).await;
+                    }
+                    Ok(Err(err)) => debug!("TLS handshake with {remote} failed: {err}"),
+                    Err(_) => debug!("TLS handshake with {remote} timed out"),
+                }
+            });
+        }
+    });
+
+    hyper::server::accept::poll_fn(move |cx| rx.poll_recv(cx).map(|stream| stream.map(Ok)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use anyhow::Result;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, SanType};
+    use rustls::{ClientConfig, RootCertStore, ServerName};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    use super::TlsConfig;
+    use crate::{auth::ClientCertificate,
+                proxy::{Flow, Forward, Handler, Proxy}};
+
+    /// Self-signed CA issuing test certificates.
+    pub(crate) fn ca() -> Certificate {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "kkowa test CA");
+
+        Certificate::from_params(params).unwrap()
+    }
+
+    /// Certificate of given subject and SANs, signed by CA. Returns PEM-encoded certificate and private key.
+    pub(crate) fn issue(
+        ca: &Certificate,
+        common_name: &str,
+        organizational_unit: Option<&str>,
+        sans: Vec<SanType>,
+    ) -> (String, String) {
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        if let Some(ou) = organizational_unit {
+            params
+                .distinguished_name
+                .push(DnType::OrganizationalUnitName, ou);
+        }
+        params.subject_alt_names = sans;
+        let cert = Certificate::from_params(params).unwrap();
+
+        (
+            cert.serialize_pem_with_signer(ca).unwrap(),
+            cert.serialize_private_key_pem(),
+        )
+    }
+
+    /// Handler replying with authenticated user ID.
+    #[derive(Debug)]
+    struct WhoAmI;
+
+    #[async_trait::async_trait]
+    impl Handler for WhoAmI {
+        async fn on_request(&self, flow: &Flow, req: crate::http::Request) -> Forward {
+            let id = flow.principal().as_ref().map(|p| p.id.clone());
+            Forward::Reply(Box::new(
+                crate::http::Response::builder()
+                    .payload(id.unwrap_or_default().into_bytes())
+                    .request(req)
+                    .build()
+                    .unwrap(),
+            ))
+        }
+    }
+
+    #[test]
+    fn config() -> Result<()> {
+        let ca = ca();
+        let (cert, key) = issue(&ca, "localhost", None, vec![]);
+
+        assert!(TlsConfig::from_pem(cert.as_bytes(), key.as_bytes())?
+            .client_ca_pem(ca.serialize_pem()?.as_bytes())?
+            .require_client_cert(true)
+            .build()
+            .is_ok());
+        assert!(matches!(
+            TlsConfig::from_pem(b"", key.as_bytes()),
+            Err(super::Error::NoCertificate)
+        ));
+        assert!(matches!(
+            TlsConfig::from_pem(cert.as_bytes(), cert.as_bytes()),
+            Err(super::Error::NoPrivateKey)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run() -> Result<()> {
+        let ca = ca();
+        let (server_cert, server_key) = issue(
+            &ca,
+            "localhost",
+            None,
+            vec![SanType::DnsName("localhost".to_string())],
+        );
+        let (client_cert, client_key) = issue(
+            &ca,
+            "billing",
+            None,
+            vec![SanType::URI("spiffe://example.com/billing".to_string())],
+        );
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], portpicker::pick_unused_port().unwrap()));
+        let proxy = Proxy::builder()
+            .tls(
+                TlsConfig::from_pem(server_cert.as_bytes(), server_key.as_bytes())?
+                    .client_ca_pem(ca.serialize_pem()?.as_bytes())?
+                    .build()?,
+            )
+            .auths(Arc::new(vec![Box::new(ClientCertificate::new())]))
+            .handlers(Arc::new(vec![Box::new(WhoAmI)]))
+            .build()?;
+        tokio::spawn(async move { proxy.run(&addr).await });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let mut roots = RootCertStore::empty();
+        for cert in super::certificates(ca.serialize_pem()?.as_bytes())? {
+            roots.add(&cert)?;
+        }
+        let request = |config: ClientConfig| async move {
+            let connector = TlsConnector::from(Arc::new(config));
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            let mut stream = connector
+                .connect(ServerName::try_from("localhost")?, stream)
+                .await?;
+            stream
+                .write_all(b"GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n")
+                .await?;
+            let mut resp = String::new();
+            stream.read_to_string(&mut resp).await?;
+
+            anyhow::Ok(resp)
+        };
+
+        // Authenticated by client certificate, without any header
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots.clone())
+            .with_single_cert(
+                super::certificates(client_cert.as_bytes())?,
+                super::private_key(client_key.as_bytes())?,
+            )?;
+        let resp = request(config).await?;
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.ends_with("spiffe://example.com/billing"));
+
+        // Anonymous clients may connect, but are challenged by proxy
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let resp = request(config).await?;
+        assert!(resp.starts_with("HTTP/1.1 407 Proxy Authentication Required"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+//! Request rate limiting, using generic cell rate algorithm (GCRA).
+//!
+//! https://en.wikipedia.org/wiki/Generic_cell_rate_algorithm
+
+use std::{collections::HashMap,
+          fmt::Debug,
+          sync::{Arc, Mutex},
+          time::{Duration, SystemTime}};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{http::{header, HeaderName, HeaderValue, Request, Response, StatusCode},
+            metrics,
+            proxy::{Flow, Forward, Handler, Matcher}};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("rate limit store failed: {0}")]
+    Backend(String),
+}
+
+/// Allowed request rate: `limit` requests per `period`, in bursts of up to `burst` requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    limit: u32,
+    period: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// Create new quota allowing bursts of whole limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if limit is zero.
+    pub fn new(limit: u32, period: Duration) -> Self {
+        assert!(limit > 0, "quota limit must be positive");
+        Self {
+            limit,
+            period,
+            burst: limit,
+        }
+    }
+
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    pub fn per_hour(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(3600))
+    }
+
+    /// Maximum number of requests allowed at once, at least one.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Interval at which single request is replenished.
+    pub fn interval(&self) -> Duration {
+        self.period / self.limit
+    }
+
+    /// How far ahead of current time theoretical arrival time may be.
+    pub fn tolerance(&self) -> Duration {
+        self.interval() * self.burst
+    }
+}
+
+/// Result of rate limit check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub allowed: bool,
+
+    /// Maximum burst size.
+    pub limit: u32,
+
+    /// Requests still allowed right now.
+    pub remaining: u32,
+
+    /// Time until quota fully replenished.
+    pub reset: Duration,
+
+    /// Time until next request allowed, if request rejected.
+    pub retry_after: Option<Duration>,
+}
+
+impl Outcome {
+    /// Compute outcome from theoretical arrival time (TAT) stored for key, returning new TAT to store if request
+    /// allowed.
+    pub fn gcra(
+        quota: &Quota,
+        tat: Option<SystemTime>,
+        now: SystemTime,
+    ) -> (Self, Option<SystemTime>) {
+        let (interval, tolerance) = (quota.interval(), quota.tolerance());
+        let tat = tat.map_or(now, |tat| tat.max(now));
+        let new_tat = tat + interval;
+        let allow_at = new_tat.checked_sub(tolerance).unwrap_or(now);
+        let until = |t: SystemTime| t.duration_since(now).unwrap_or_default();
+
+        if allow_at > now {
+            let outcome = Self {
+                allowed: false,
+                limit: quota.burst,
+                remaining: 0,
+                reset: until(tat),
+                retry_after: Some(until(allow_at)),
+            };
+            return (outcome, None);
+        }
+
+        let used = until(new_tat);
+        let remaining = tolerance
+            .saturating_sub(used)
+            .as_nanos()
+            .checked_div(interval.as_nanos())
+            .unwrap_or_default();
+        let outcome = Self {
+            allowed: true,
+            limit: quota.burst,
+            remaining: u32::try_from(remaining).unwrap_or(u32::MAX),
+            reset: used,
+            retry_after: None,
+        };
+
+        (outcome, Some(new_tat))
+    }
+}
+
+/// Storage of rate limiter state. Shared stores let multiple proxy instances enforce common limits; implementations
+/// must apply [`Outcome::gcra`] atomically per key.
+#[async_trait]
+pub trait Store: Debug + Send + Sync {
+    /// Count request against key, returning whether it is allowed.
+    async fn acquire(&self, key: &str, quota: &Quota, now: SystemTime) -> Result<Outcome, Error>;
+}
+
+#[derive(Debug, Default)]
+struct Tats {
+    /// Theoretical arrival time per key.
+    tats: HashMap<String, SystemTime>,
+    pruned_at: Option<SystemTime>,
+}
+
+/// In-process rate limiter state.
+#[derive(Debug, Default)]
+pub struct Memory {
+    tats: Mutex<Tats>,
+}
+
+impl Memory {
+    /// Number of keys at which elapsed ones are pruned.
+    const PRUNE_THRESHOLD: usize = 10_000;
+
+    /// Minimum time between prunes, so that many live keys do not make every request scan all of them.
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Store for Memory {
+    async fn acquire(&self, key: &str, quota: &Quota, now: SystemTime) -> Result<Outcome, Error> {
+        let mut state = self.tats.lock().unwrap();
+        let Tats { tats, pruned_at } = &mut *state;
+        if tats.len() >= Self::PRUNE_THRESHOLD
+            && pruned_at.map_or(true, |at| at + Self::PRUNE_INTERVAL <= now)
+        {
+            // Keys whose TAT has passed are indistinguishable from new ones
+            tats.retain(|_, tat| *tat > now);
+            *pruned_at = Some(now);
+        }
+
+        let (outcome, tat) = Outcome::gcra(quota, tats.get(key).copied(), now);
+        if let Some(tat) = tat {
+            tats.insert(key.to_string(), tat);
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// What requests are counted against.
+#[derive(Clone, Debug)]
+pub enum Key {
+    /// Authenticated user ID; anonymous requests are not limited.
+    User,
+
+    /// Client IP address.
+    Client,
+
+    /// Destination host.
+    Host,
+
+    /// Value of request header; requests without it are not limited. Clients choose header values, so they can
+    /// dodge limit by varying or omitting header; only use with headers set by trusted party, such as API key checked
+    /// upstream or client identity set by front proxy.
+    Header(HeaderName),
+}
+
+impl Key {
+    /// Resolve key of request, prefixed by its kind.
+    fn resolve(&self, flow: &Flow, req: &Request) -> Option<String> {
+        match self {
+            Self::User => flow
+                .principal()
+                .as_ref()
+                .map(|principal| format!("user:{id}", id = principal.id)),
+            Self::Client => Some(format!("client:{ip}", ip = flow.client().ip())),
+            Self::Host => req.uri.host().map(|host| format!("host:{host}")),
+            Self::Header(name) => req
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| format!("header:{name}:{value}")),
+        }
+    }
+}
+
+/// Rate limiter rejecting requests over quota with `429 Too Many Requests`, usable as handler or as proxy-wide limit
+/// applied right after authorization.
+///
+/// Rejections carry `Retry-After` and `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers as of
+/// draft IETF RateLimit header fields. If store fails, requests are let through.
+#[derive(Debug)]
+pub struct RateLimit {
+    key: Key,
+    quota: Quota,
+    matcher: Option<Matcher>,
+    store: Arc<dyn Store>,
+}
+
+impl RateLimit {
+    /// Create new rate limiter with in-memory state, applied to all requests.
+    pub fn new(key: Key, quota: Quota) -> Self {
+        Self {
+            key,
+            quota,
+            matcher: None,
+            store: Arc::new(Memory::new()),
+        }
+    }
+
+    /// Limit only requests matching matcher.
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Keep state in given store. Limiters sharing store must use different key kinds.
+    pub fn store(mut self, store: Arc<dyn Store>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Count request against its key, returning outcome, or `None` if request is not limited.
+    pub async fn check(&self, flow: &Flow, req: &Request) -> Option<Outcome> {
+        if let Some(matcher) = &self.matcher {
+            if !matcher.matches(flow, req) {
+                return None;
+            }
+        }
+        let key = self.key.resolve(flow, req)?;
+
+        match self
+            .store
+            .acquire(&key, &self.quota, SystemTime::now())
+            .await
+        {
+            Ok(outcome) => {
+                if !outcome.allowed {
+                    debug!("rate limit exceeded for {key}");
+                    metrics::RATE_LIMITED_COUNTER.increment(1);
+                }
+                Some(outcome)
+            }
+            Err(err) => {
+                warn!("failed to check rate limit for {key}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Response rejecting request over quota.
+    pub fn reject(outcome: &Outcome, req: Request) -> Response {
+        let secs = |d: Duration| HeaderValue::from(d.as_secs() + u64::from(d.subsec_nanos() > 0));
+        let mut builder = Response::builder();
+        builder
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(
+                HeaderName::from_static("ratelimit-limit"),
+                HeaderValue::from(outcome.limit),
+            )
+            .header(
+                HeaderName::from_static("ratelimit-remaining"),
+                HeaderValue::from(outcome.remaining),
+            )
+            .header(
+                HeaderName::from_static("ratelimit-reset"),
+                secs(outcome.reset),
+            );
+        if let Some(retry_after) = outcome.retry_after {
+            builder.header(header::RETRY_AFTER, secs(retry_after));
+        }
+
+        builder
+            .payload(b"rate limit exceeded".to_vec())
+            .request(req)
+            .build()
+            .unwrap()
+    }
+}
+
+#[async_trait]
+impl Handler for RateLimit {
+    async fn on_request(&self, flow: &Flow, req: Request) -> Forward {
+        match self.check(flow, &req).await {
+            Some(outcome) if !outcome.allowed => {
+                Forward::Reply(Box::new(Self::reject(&outcome, req)))
+            }
+            _ => Forward::DoNothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr,
+              str::FromStr,
+              time::{Duration, SystemTime, UNIX_EPOCH}};
+
+    use anyhow::Result;
+
+    use super::{Key, Memory, Outcome, Quota, RateLimit, Store};
+    use crate::{auth::Principal,
+                http::{header, HeaderName, Request, StatusCode},
+                proxy::{Forward, Handler, Matcher},
+                Proxy};
+
+    #[tokio::test]
+    async fn memory() -> Result<()> {
+        let store = Memory::new();
+        let quota = Quota::per_second(2);
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let outcome = store.acquire("a", &quota, now).await?;
+        assert_eq!(
+            outcome,
+            Outcome {
+                allowed: true,
+                limit: 2,
+                remaining: 1,
+                reset: Duration::from_millis(500),
+                retry_after: None
+            }
+        );
+        assert_eq!(store.acquire("a", &quota, now).await?.remaining, 0);
+
+        let outcome = store.acquire("a", &quota, now).await?;
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.reset, Duration::from_secs(1));
+        assert_eq!(outcome.retry_after, Some(Duration::from_millis(500)));
+
+        // Other keys are independent
+        assert!(store.acquire("b", &quota, now).await?.allowed);
+
+        // Single request replenished after interval, whole burst after period
+        let later = now + Duration::from_millis(500);
+        assert!(store.acquire("a", &quota, later).await?.allowed);
+        assert!(!store.acquire("a", &quota, later).await?.allowed);
+        let later = later + Duration::from_secs(1);
+        assert_eq!(store.acquire("a", &quota, later).await?.remaining, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_prune() -> Result<()> {
+        let store = Memory::new();
+        let quota = Quota::per_second(10);
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for i in 0..Memory::PRUNE_THRESHOLD {
+            store.acquire(&i.to_string(), &quota, now).await?;
+        }
+
+        // Elapsed keys are pruned once full, then not again within interval
+        let later = now + Duration::from_secs(1);
+        store.acquire("a", &quota, later).await?;
+        assert_eq!(store.tats.lock().unwrap().tats.len(), 1);
+        for i in 0..Memory::PRUNE_THRESHOLD {
+            store.acquire(&i.to_string(), &quota, later).await?;
+        }
+        store
+            .acquire("b", &quota, later + Duration::from_millis(500))
+            .await?;
+        assert_eq!(
+            store.tats.lock().unwrap().tats.len(),
+            Memory::PRUNE_THRESHOLD + 2
+        );
+        store
+            .acquire("c", &quota, later + Memory::PRUNE_INTERVAL)
+            .await?;
+        assert_eq!(store.tats.lock().unwrap().tats.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn burst() -> Result<()> {
+        let store = Memory::new();
+        let quota = Quota::per_minute(60).burst(1);
+        let now = SystemTime::now();
+
+        assert!(store.acquire("a", &quota, now).await?.allowed);
+        let outcome = store.acquire("a", &quota, now).await?;
+        assert!(!outcome.allowed);
+        assert_eq!(outcome.retry_after, Some(Duration::from_secs(1)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn on_request() -> Result<()> {
+        let mut flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let req = Request::builder()
+            .uri("http://example.com/".parse()?)
+            .build()?;
+        let limit = RateLimit::new(Key::Client, Quota::per_hour(1));
+
+        assert!(matches!(
+            limit.on_request(&flow, req.clone()).await,
+            Forward::DoNothing
+        ));
+        match limit.on_request(&flow, req.clone()).await {
+            Forward::Reply(resp) => {
+                assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(resp.headers[header::RETRY_AFTER], "3600");
+                assert_eq!(resp.headers["ratelimit-limit"], "1");
+                assert_eq!(resp.headers["ratelimit-remaining"], "0");
+                assert_eq!(resp.headers["ratelimit-reset"], "3600");
+            }
+            _ => panic!("request not rejected"),
+        }
+
+        // Anonymous requests are not limited per user, nor requests not matching
+        let limit = RateLimit::new(Key::User, Quota::per_hour(1));
+        for _ in 0..2 {
+            assert!(matches!(
+                limit.on_request(&flow, req.clone()).await,
+                Forward::DoNothing
+            ));
+        }
+        *flow.principal_mut() = Some(Principal::builder().id("alice").build()?);
+        assert!(matches!(
+            limit.on_request(&flow, req.clone()).await,
+            Forward::DoNothing
+        ));
+        assert!(matches!(
+            limit.on_request(&flow, req.clone()).await,
+            Forward::Reply(_)
+        ));
+
+        let limit = RateLimit::new(Key::Host, Quota::per_hour(1))
+            .matcher(Matcher::Host("*.internal".to_string()));
+        for _ in 0..2 {
+            assert!(matches!(
+                limit.on_request(&flow, req.clone()).await,
+                Forward::DoNothing
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn header_key() -> Result<()> {
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let name = HeaderName::from_static("x-api-key");
+        let limit = RateLimit::new(Key::Header(name.clone()), Quota::per_hour(1));
+        let request = |key: &str| -> Result<Request> {
+            let mut req = Request::builder()
+                .uri("http://example.com/".parse()?)
+                .build()?;
+            req.headers.insert(name.clone(), key.parse()?);
+            Ok(req)
+        };
+
+        assert!(limit.check(&flow, &request("a")?).await.unwrap().allowed);
+        assert!(!limit.check(&flow, &request("a")?).await.unwrap().allowed);
+        assert!(limit.check(&flow, &request("b")?).await.unwrap().allowed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Replay recorded exchanges against live or alternate upstreams, comparing new responses with recorded ones.
+
+use std::{fs, path::Path};
+
+use derive_builder::Builder;
+use http::uri::Authority;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{http::{header, remove_hop_by_hop_headers, Diff, HeaderName, Request, Response},
+            proxy::Client,
+            vcr::Interaction};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read exchanges file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to deserialize exchanges: {0}")]
+    Serde(#[from] serde_json::Error),
+
+    #[error("failed to rewrite request URI: {0}")]
+    InvalidUri(#[from] http::Error),
+
+    #[error("failed to send request: {0}")]
+    Request(#[from] hyper::Error),
+}
+
+/// Recorded pair of request and response.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: Request,
+    pub response: Response,
+}
+
+impl Exchange {
+    pub fn new(request: Request, response: Response) -> Self {
+        Self { request, response }
+    }
+
+    /// Load exchanges from JSON file containing an array of exchanges.
+    pub fn load_all<P>(path: P) -> Result<Vec<Self>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path)?;
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+impl From<Interaction> for Exchange {
+    fn from(interaction: Interaction) -> Self {
+        Self::new(interaction.request, interaction.response)
+    }
+}
+
+/// Result of replaying single exchange.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    /// Request actually sent, after rewrite.
+    pub request: Request,
+
+    /// Response recorded originally.
+    pub expected: Response,
+
+    /// Response received on replay.
+    pub actual: Response,
+
+    pub diff: Diff,
+}
+
+/// Re-sends recorded requests, optionally to different upstream, and compares responses.
+#[derive(Clone, Debug, Builder)]
+#[builder(default)]
+pub struct Replayer {
+    client: Client,
+
+    /// Upstream authority (host and port) to send requests to instead of recorded one.
+    #[builder(setter(strip_option))]
+    upstream: Option<Authority>,
+
+    /// Headers excluded from response comparison.
+    #[builder(setter(each(name = "ignore_header")))]
+    ignore_headers: Vec<HeaderName>,
+}
+
+impl Default for Replayer {
+    /// Replay to recorded upstreams, ignoring `Date` header on comparison.
+    fn default() -> Self {
+        Self {
+            client: Client::default(),
+            upstream: None,
+            ignore_headers: vec![header::DATE],
+        }
+    }
+}
+
+impl Replayer {
+    pub fn builder() -> ReplayerBuilder {
+        ReplayerBuilder::default()
+    }
+
+    /// Replay single exchange.
+    pub async fn replay(&self, exchange: &Exchange) -> Result<Outcome, Error> {
+        let mut req = exchange.request.clone();
+        if let Some(upstream) = &self.upstream {
+            req.set_authority(upstream)?;
+        }
+        remove_hop_by_hop_headers(&mut req.headers);
+
+        debug!(
+            "replaying {method} {uri}",
+            method = req.method,
+            uri = req.uri
+        );
+        let resp = self.client.request(req.clone().into()).await?;
+        let actual = Response::from(resp, req.clone()).await;
+        let diff = Diff::between(&exchange.response, &actual, &self.ignore_headers);
+
+        Ok(Outcome {
+            request: req,
+            expected: exchange.response.clone(),
+            actual,
+            diff,
+        })
+    }
+
+    /// Replay all exchanges sequentially.
+    pub async fn replay_all(&self, exchanges: &[Exchange]) -> Result<Vec<Outcome>, Error> {
+        let mut outcomes = Vec::with_capacity(exchanges.len());
+        for exchange in exchanges {
+            outcomes.push(self.replay(exchange).await?);
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use httpmock::prelude::*;
+
+    use super::{Exchange, Replayer};
+    use crate::http::{header, Request, Response, StatusCode};
+
+    fn exchange(uri: &str) -> Result<Exchange> {
+        Ok(Exchange::new(
+            Request::builder()
+                .uri(uri.parse()?)
+                .header(header::HOST, "example.com".parse()?)
+                .build()?,
+            Response::builder()
+                .status(StatusCode::OK)
+                .payload(b"Hello World!".to_vec())
+                .build()?,
+        ))
+    }
+
+    #[tokio::test]
+    async fn replay_to_alternate_upstream() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/hello-world")
+                .header("host", server.address().to_string());
+            then.status(200).body("Good Evening");
+        });
+
+        let replayer = Replayer::builder()
+            .upstream(server.address().to_string().parse()?)
+            .build()?;
+        let outcome = replayer
+            .replay(&exchange("http://example.com/hello-world")?)
+            .await?;
+
+        mock.assert();
+        assert_eq!(outcome.actual.status, StatusCode::OK);
+        assert!(outcome.diff.status.is_none());
+        assert!(outcome.diff.body.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_identical() -> Result<()> {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/hello-world");
+            then.status(200).body("Hello World!");
+        });
+
+        let outcome = Replayer::default()
+            .replay(&exchange(&server.url("/hello-world"))?)
+            .await?;
+
+        assert!(
+            outcome.diff.headers.iter().all(|h| h.name != "date"),
+            "date header must be ignored by default"
+        );
+        assert!(outcome.diff.body.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_all() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "kkowa-exchanges-{pid}.json",
+            pid = std::process::id()
+        ));
+        let exchanges = vec![exchange("http://example.com/")?];
+        std::fs::write(&path, serde_json::to_vec(&exchanges)?)?;
+
+        let loaded = Exchange::load_all(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded, exchanges);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+//! Cassette file storing recorded interactions.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::Error;
+use crate::http::{digest, Request, Response};
+
+/// Single recorded exchange of request and response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: Request,
+    pub response: Response,
+
+    /// Hex-encoded SHA-256 digest of request payload, used for body matching.
+    pub body_digest: String,
+}
+
+impl Interaction {
+    pub fn new(request: Request, response: Response) -> Self {
+        Self {
+            body_digest: digest(&request.payload),
+            request,
+            response,
+        }
+    }
+}
+
+/// Collection of recorded interactions, persisted as JSON file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load cassette from given file path.
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path)?;
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Save cassette to given file path, overwriting existing one.
+    pub fn save<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data = serde_json::to_vec_pretty(self)?;
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{Cassette, Interaction};
+    use crate::http::{header, Method, Request, Response, StatusCode};
+
+    #[test]
+    fn save_and_load() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "kkowa-cassette-{pid}.json",
+            pid = std::process::id()
+        ));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/echo".parse()?)
+            .payload(b"Hello World!".to_vec())
+            .build()?;
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header(header::CONTENT_TYPE, "text/plain".parse()?)
+            .payload(b"Good Evening".to_vec())
+            .build()?;
+        let cassette = Cassette {
+            interactions: vec![Interaction::new(request, response)],
+        };
+
+        cassette.save(&path)?;
+        let loaded = Cassette::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded, cassette);
+
+        Ok(())
+    }
+}
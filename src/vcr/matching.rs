@@ -0,0 +1,230 @@
+//! Rules to match incoming requests against recorded interactions.
+
+use derive_builder::Builder;
+
+use super::cassette::Interaction;
+use crate::http::{digest, remove_hop_by_hop_headers, HeaderName, Request, Uri};
+
+/// Configurable rules deciding whether an incoming request matches a recorded one.
+#[derive(Clone, Debug, Builder)]
+#[builder(default)]
+pub struct Matching {
+    /// Compare request methods.
+    method: bool,
+
+    /// Compare request URIs, except for query parameters listed in `ignore_query`.
+    uri: bool,
+
+    /// Compare request headers, except for ones listed in `ignore_headers` and hop-by-hop ones, which recorded requests
+    /// do not have as they are stripped before forwarding.
+    headers: bool,
+
+    /// Compare digest of request bodies.
+    body: bool,
+
+    /// Header names excluded from comparison.
+    #[builder(setter(each(name = "ignore_header")))]
+    ignore_headers: Vec<HeaderName>,
+
+    /// Query parameter names excluded from comparison.
+    #[builder(setter(each(name = "ignore_query_param", into)))]
+    ignore_query: Vec<String>,
+
+    /// In auto mode, fail requests which no recorded interaction matches, rather than passing them through to remote
+    /// and recording exchange. Replay mode always fails them.
+    strict: bool,
+}
+
+impl Default for Matching {
+    /// Match by method and URI, non-strict.
+    fn default() -> Self {
+        Self {
+            method: true,
+            uri: true,
+            headers: false,
+            body: false,
+            ignore_headers: vec![],
+            ignore_query: vec![],
+            strict: false,
+        }
+    }
+}
+
+impl Matching {
+    pub fn builder() -> MatchingBuilder {
+        MatchingBuilder::default()
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Check whether given request matches recorded interaction.
+    pub fn matches(&self, req: &Request, interaction: &Interaction) -> bool {
+        let recorded = &interaction.request;
+
+        if self.method && req.method != recorded.method {
+            return false;
+        }
+
+        if self.uri && self.normalize_uri(&req.uri) != self.normalize_uri(&recorded.uri) {
+            return false;
+        }
+
+        if self.headers {
+            let filter = |req: &Request| {
+                let mut headers = req.headers.clone();
+                remove_hop_by_hop_headers(&mut headers);
+                let mut headers: Vec<(String, Vec<u8>)> = headers
+                    .iter()
+                    .filter(|(k, _)| !self.ignore_headers.contains(k))
+                    .map(|(k, v)| (k.to_string(), v.as_bytes().to_vec()))
+                    .collect();
+                headers.sort();
+
+                headers
+            };
+
+            if filter(req) != filter(recorded) {
+                return false;
+            }
+        }
+
+        if self.body && digest(&req.payload) != interaction.body_digest {
+            return false;
+        }
+
+        true
+    }
+
+    /// Strip ignored query parameters from URI, returning comparable string form.
+    fn normalize_uri(&self, uri: &Uri) -> String {
+        let base = format!(
+            "{scheme}://{authority}{path}",
+            scheme = uri.scheme_str().unwrap_or_default(),
+            authority = uri.authority().map(|a| a.as_str()).unwrap_or_default(),
+            path = uri.path()
+        );
+
+        match uri.query() {
+            Some(query) => {
+                let mut params: Vec<&str> = query
+                    .split('&')
+                    .filter(|param| {
+                        let name = param.split('=').next().unwrap_or_default();
+                        !self.ignore_query.iter().any(|ignored| ignored == name)
+                    })
+                    .collect();
+                params.sort_unstable();
+
+                format!("{base}?{query}", query = params.join("&"))
+            }
+            None => base,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::Matching;
+    use crate::{http::{header, Method, Request, Response},
+                vcr::Interaction};
+
+    fn recorded(method: Method, uri: &str, payload: &[u8]) -> Result<Interaction> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri.parse()?)
+            .header(header::USER_AGENT, "curl/7.86.0".parse()?)
+            .payload(payload.to_vec())
+            .build()?;
+
+        Ok(Interaction::new(request, Response::default()))
+    }
+
+    #[test]
+    fn method_and_uri() -> Result<()> {
+        let matching = Matching::default();
+        let interaction = recorded(Method::GET, "http://example.com/get?a=1&b=2", b"")?;
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("http://example.com/get?b=2&a=1".parse()?)
+            .build()?;
+        assert!(matching.matches(&req, &interaction));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/get?a=1&b=2".parse()?)
+            .build()?;
+        assert!(!matching.matches(&req, &interaction));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_query() -> Result<()> {
+        let matching = Matching::builder().ignore_query_param("ts").build()?;
+        let interaction = recorded(Method::GET, "http://example.com/get?a=1&ts=100", b"")?;
+
+        let req = Request::builder()
+            .uri("http://example.com/get?ts=200&a=1".parse()?)
+            .build()?;
+
+        assert!(matching.matches(&req, &interaction));
+
+        Ok(())
+    }
+
+    #[test]
+    fn headers() -> Result<()> {
+        let interaction = recorded(Method::GET, "http://example.com/", b"")?;
+        let req = Request::builder()
+            .uri("http://example.com/".parse()?)
+            .header(header::USER_AGENT, "Mozilla/5.0".parse()?)
+            .build()?;
+
+        let matching = Matching::builder().headers(true).build()?;
+        assert!(!matching.matches(&req, &interaction));
+
+        let matching = Matching::builder()
+            .headers(true)
+            .ignore_header(header::USER_AGENT)
+            .build()?;
+        assert!(matching.matches(&req, &interaction));
+
+        // Hop-by-hop headers of live request are not compared
+        let req = Request::builder()
+            .uri("http://example.com/".parse()?)
+            .header(header::USER_AGENT, "curl/7.86.0".parse()?)
+            .header(header::PROXY_AUTHORIZATION, "Basic Zm9vOmJhcg==".parse()?)
+            .build()?;
+        let matching = Matching::builder().headers(true).build()?;
+        assert!(matching.matches(&req, &interaction));
+
+        Ok(())
+    }
+
+    #[test]
+    fn body() -> Result<()> {
+        let matching = Matching::builder().body(true).build()?;
+        let interaction = recorded(Method::POST, "http://example.com/", b"Hello World!")?;
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/".parse()?)
+            .payload(b"Hello World!".to_vec())
+            .build()?;
+        assert!(matching.matches(&req, &interaction));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/".parse()?)
+            .payload(b"Good Evening".to_vec())
+            .build()?;
+        assert!(!matching.matches(&req, &interaction));
+
+        Ok(())
+    }
+}
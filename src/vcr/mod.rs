@@ -0,0 +1,317 @@
+//! Record-and-replay (VCR) support for offline testing.
+//!
+//! Recorder handler collects every upstream exchange into a cassette file, and serves matching responses from it
+//! later on without touching the network.
+
+mod cassette;
+mod matching;
+
+use std::{path::{Path, PathBuf},
+          sync::Mutex};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::{debug, error, warn};
+
+pub use self::{cassette::{Cassette, Interaction},
+               matching::{Matching, MatchingBuilder}};
+use crate::{http::{Request, Response, StatusCode},
+            proxy::{Flow, Forward, Handler, Reverse}};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("cassette file {0} does not exist")]
+    CassetteNotFound(PathBuf),
+
+    #[error("failed to read or write cassette file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to (de)serialize cassette: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Operation mode of recorder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Always forward requests to remote and record all exchanges.
+    Record,
+
+    /// Serve responses from cassette only, failing requests no recorded interaction matches with
+    /// `502 Bad Gateway`.
+    Replay,
+
+    /// Serve matching responses from cassette, record exchanges which have no matching one.
+    Auto,
+}
+
+/// Handler recording exchanges into, or replaying them from, a cassette file.
+///
+/// Recorded exchanges are kept in memory and written to file on [`Recorder::flush`] or once recorder is dropped.
+/// Share recorder through [`Arc`](std::sync::Arc) to flush it while proxy is running.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    mode: Mode,
+    matching: Matching,
+
+    cassette: Mutex<Cassette>,
+
+    /// Whether each interaction has been replayed already, to serve repeated requests in recorded order.
+    played: Mutex<Vec<bool>>,
+
+    /// Number of interactions in cassette when last saved.
+    saved: Mutex<usize>,
+}
+
+impl Recorder {
+    /// Create new recorder, loading cassette at given path if exists.
+    ///
+    /// Replay mode requires cassette file to exist. Record mode always starts with empty cassette.
+    pub fn new<P>(path: P, mode: Mode, matching: Matching) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let cassette = match (mode, path.exists()) {
+            (Mode::Record, _) => Cassette::new(),
+            (_, true) => Cassette::load(&path)?,
+            (Mode::Replay, false) => return Err(Error::CassetteNotFound(path)),
+            (Mode::Auto, false) => Cassette::new(),
+        };
+        let played = vec![false; cassette.interactions.len()];
+        let saved = cassette.interactions.len();
+
+        Ok(Self {
+            path,
+            mode,
+            matching,
+            cassette: Mutex::new(cassette),
+            played: Mutex::new(played),
+            saved: Mutex::new(saved),
+        })
+    }
+
+    /// Snapshot of current cassette.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    /// Find recorded response for request. Prefers interactions not replayed yet, then falls back to the last match.
+    fn lookup(&self, req: &Request) -> Option<Response> {
+        let cassette = self.cassette.lock().unwrap();
+        let mut played = self.played.lock().unwrap();
+
+        let matches: Vec<usize> = cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| self.matching.matches(req, interaction))
+            .map(|(i, _)| i)
+            .collect();
+
+        let idx = matches
+            .iter()
+            .find(|&&i| !played[i])
+            .or_else(|| matches.last())
+            .copied()?;
+        played[idx] = true;
+
+        let mut response = cassette.interactions[idx].response.clone();
+        response.request = req.clone();
+
+        Some(response)
+    }
+
+    /// Write cassette to file, if any exchange was recorded since last write.
+    pub fn flush(&self) -> Result<(), Error> {
+        let cassette = self.cassette.lock().unwrap();
+        let mut saved = self.saved.lock().unwrap();
+        if *saved == cassette.interactions.len() {
+            return Ok(());
+        }
+        cassette.save(&self.path)?;
+        *saved = cassette.interactions.len();
+
+        Ok(())
+    }
+
+    /// Add exchange to cassette.
+    fn record(&self, resp: &Response) {
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette
+            .interactions
+            .push(Interaction::new(resp.request.clone(), resp.clone()));
+        self.played.lock().unwrap().push(true);
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("failed to save cassette {path:?}: {err}", path = self.path);
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for Recorder {
+    async fn on_request(&self, _flow: &Flow, req: Request) -> Forward {
+        if self.mode == Mode::Record {
+            return Forward::DoNothing;
+        }
+
+        if let Some(resp) = self.lookup(&req) {
+            debug!("replaying recorded response for {uri}", uri = req.uri);
+            return Forward::Reply(Box::new(resp));
+        }
+
+        if self.mode == Mode::Replay || self.matching.is_strict() {
+            warn!(
+                "no recorded interaction matches {method} {uri}",
+                method = req.method,
+                uri = req.uri
+            );
+            let resp = Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .payload(b"no matching interaction found in cassette".to_vec())
+                .request(req)
+                .build()
+                .unwrap();
+
+            return Forward::Reply(Box::new(resp));
+        }
+
+        Forward::DoNothing
+    }
+
+    async fn on_response(&self, _flow: &Flow, resp: Response) -> Reverse {
+        if self.mode == Mode::Replay {
+            return Reverse::DoNothing;
+        }
+
+        self.record(&resp);
+
+        Reverse::DoNothing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+
+    use anyhow::Result;
+    use httpmock::prelude::*;
+
+    use super::{Cassette, Error, Matching, Mode, Recorder};
+    use crate::{http::{Request, Response, StatusCode},
+                proxy::{Forward, Handler, Reverse},
+                Proxy};
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "kkowa-vcr-{name}-{pid}.json",
+            pid = std::process::id()
+        ))
+    }
+
+    #[test]
+    fn replay_requires_cassette() {
+        let path = cassette_path("missing");
+
+        assert!(matches!(
+            Recorder::new(path, Mode::Replay, Matching::default()),
+            Err(Error::CassetteNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn record_then_replay() -> Result<()> {
+        let path = cassette_path("record-then-replay");
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let request = Request::builder()
+            .uri("http://example.com/get".parse()?)
+            .build()?;
+        let response = Response::builder()
+            .payload(b"Hello World!".to_vec())
+            .request(request.clone())
+            .build()?;
+
+        // Record
+        let recorder = Recorder::new(&path, Mode::Record, Matching::default())?;
+        assert!(matches!(
+            recorder.on_request(&flow, request.clone()).await,
+            Forward::DoNothing
+        ));
+        assert!(matches!(
+            recorder.on_response(&flow, response.clone()).await,
+            Reverse::DoNothing
+        ));
+        assert_eq!(recorder.cassette().interactions.len(), 1);
+        assert!(!path.exists());
+        drop(recorder);
+
+        // Replay
+        let recorder = Recorder::new(&path, Mode::Replay, Matching::default())?;
+        std::fs::remove_file(&path)?;
+
+        match recorder.on_request(&flow, request).await {
+            Forward::Reply(resp) => assert_eq!(*resp, response),
+            _ => panic!("expected recorded response replayed"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_unmatched() -> Result<()> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.any_request();
+            then.status(200);
+        });
+        let path = cassette_path("replay-unmatched");
+        Cassette::new().save(&path)?;
+
+        // Replay never passes requests through, even if not strict
+        let recorder = Recorder::new(&path, Mode::Replay, Matching::default())?;
+        std::fs::remove_file(&path)?;
+        let proxy = Proxy::new(
+            "proxy",
+            Default::default(),
+            vec![],
+            vec![Box::new(recorder)],
+        );
+        let req = hyper::Request::builder()
+            .uri(server.url("/get"))
+            .body(hyper::Body::empty())?;
+        let resp = crate::proxy::tests::serve(&proxy, req).await?;
+
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        mock.assert_hits(0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_strict_unmatched() -> Result<()> {
+        let path = cassette_path("strict");
+        let flow = Proxy::default().flow(SocketAddr::from_str("127.0.0.1:65535")?);
+        let request = Request::builder()
+            .uri("http://example.com/get".parse()?)
+            .build()?;
+
+        let recorder = Recorder::new(&path, Mode::Auto, Matching::default())?;
+        assert!(matches!(
+            recorder.on_request(&flow, request.clone()).await,
+            Forward::DoNothing
+        ));
+
+        let recorder = Recorder::new(&path, Mode::Auto, Matching::builder().strict(true).build()?)?;
+        match recorder.on_request(&flow, request).await {
+            Forward::Reply(resp) => assert_eq!(resp.status, StatusCode::BAD_GATEWAY),
+            _ => panic!("expected strict mode to fail unmatched request"),
+        }
+
+        Ok(())
+    }
+}
@@ -1,23 +1,44 @@
 use std::{convert::Infallible, net::SocketAddr};
 
 use hyper::{service::{make_service_fn, service_fn},
-            Error, StatusCode};
-use tracing::info;
+            Error, Method, StatusCode};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{proxy::{self, Pipeline},
+            Proxy};
 
 /// HTTP server instance for internal purpose, such as serving health checks, metrics, etc.
+///
+/// If proxy is set, also serves admin routes below; bind server to trusted interfaces only, as they are not
+/// authenticated.
+///
+/// - `GET /pipeline`: Current pipeline of proxy.
+/// - `POST /pipeline/reload`: Reload pipeline from proxy's pipeline source, applying to new requests.
 #[derive(Clone, Debug, Default)]
-pub struct Web {}
+pub struct Web {
+    proxy: Option<Proxy>,
+}
 
 impl Web {
     pub fn new() -> Self {
-        Self {}
+        Self { proxy: None }
+    }
+
+    /// Administer given proxy, or its clone.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
     }
 
     pub async fn run(&self, addr: &SocketAddr) -> Result<(), Error> {
-        let make_service = make_service_fn(move |_| async move {
-            let service = service_fn(serve);
+        let make_service = make_service_fn(move |_| {
+            let web = self.clone();
+            async move {
+                let service = service_fn(move |req| serve(web.clone(), req));
 
-            Ok::<_, Infallible>(service)
+                Ok::<_, Infallible>(service)
+            }
         });
 
         hyper::Server::bind(addr)
@@ -36,6 +57,7 @@ async fn graceful_shutdown(&self) {
 }
 
 async fn serve(
+    web: Web,
     req: hyper::Request<hyper::Body>,
 ) -> Result<hyper::Response<hyper::Body>, hyper::Error> {
     let (version, method, uri) = (
@@ -47,13 +69,46 @@ async fn serve(
     info!("{version:?} {method} {uri}");
 
     // Simple route implementation
+    match (method, req.uri().path(), web.proxy) {
+        (Method::GET, "/pipeline", Some(proxy)) => Ok(pipeline(&proxy.pipeline())),
+        (Method::POST, "/pipeline/reload", Some(proxy)) => reload(&proxy).await,
 
-    match (method, uri) {
         // Fallback
-        (_, _) => not_found().await,
+        (_, _, _) => not_found().await,
     }
 }
 
+/// Summary of pipeline, leaving out handler details as they may hold secrets.
+fn pipeline(pipeline: &Pipeline) -> hyper::Response<hyper::Body> {
+    let summary = json!({
+        "generation": pipeline.generation(),
+        "auths": pipeline.auths().len(),
+        "handlers": pipeline.handlers().len(),
+    });
+
+    hyper::Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(summary.to_string().into())
+        .unwrap()
+}
+
+async fn reload(proxy: &Proxy) -> Result<hyper::Response<hyper::Body>, hyper::Error> {
+    let err = match proxy.reload().await {
+        Ok(reloaded) => return Ok(pipeline(&reloaded)),
+        Err(err) => err,
+    };
+    warn!("{err}");
+    let status = match err {
+        proxy::Error::NoPipelineSource => StatusCode::NOT_IMPLEMENTED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    Ok(hyper::Response::builder()
+        .status(status)
+        .body(err.to_string().into())
+        .unwrap())
+}
+
 async fn not_found() -> Result<hyper::Response<hyper::Body>, hyper::Error> {
     Ok(hyper::Response::builder()
         .status(StatusCode::NOT_FOUND)
@@ -63,8 +118,82 @@ async fn not_found() -> Result<hyper::Response<hyper::Body>, hyper::Error> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{atomic::{AtomicBool, Ordering},
+                    Arc};
+
     use anyhow::Result;
-    use hyper::{body::to_bytes, StatusCode};
+    use async_trait::async_trait;
+    use hyper::{body::to_bytes, Body, Method, Request, StatusCode};
+    use serde_json::{json, Value};
+
+    use super::Web;
+    use crate::{proxy::{handler::Dummy, Error, Pipeline, PipelineSource},
+                Proxy};
+
+    /// Pipeline source failing on demand.
+    #[derive(Debug, Default)]
+    struct Source {
+        fail: AtomicBool,
+    }
+
+    #[async_trait]
+    impl PipelineSource for Source {
+        async fn load(&self) -> Result<Pipeline, Error> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::Pipeline("broken configuration".to_string()));
+            }
+
+            Ok(Pipeline::new(vec![], vec![Box::new(Dummy)]))
+        }
+    }
+
+    async fn call(web: &Web, method: Method, path: &str) -> Result<(StatusCode, Vec<u8>)> {
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())?;
+        let resp = super::serve(web.clone(), req).await?;
+
+        Ok((resp.status(), to_bytes(resp.into_body()).await?.to_vec()))
+    }
+
+    #[tokio::test]
+    async fn pipeline() -> Result<()> {
+        let source = Arc::new(Source::default());
+        let proxy = Proxy::builder().pipeline_source(source.clone()).build()?;
+        let web = Web::new().proxy(proxy.clone());
+
+        let (status, body) = call(&web, Method::GET, "/pipeline").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body)?,
+            json!({"generation": 0, "auths": 0, "handlers": 0})
+        );
+
+        let (status, body) = call(&web, Method::POST, "/pipeline/reload").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body)?,
+            json!({"generation": 1, "auths": 0, "handlers": 1})
+        );
+        assert_eq!(proxy.pipeline().handlers().len(), 1);
+
+        // Failed reload keeps current pipeline
+        source.fail.store(true, Ordering::SeqCst);
+        let (status, _) = call(&web, Method::POST, "/pipeline/reload").await?;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(proxy.pipeline().generation(), 1);
+
+        let web = Web::new().proxy(Proxy::default());
+        let (status, _) = call(&web, Method::POST, "/pipeline/reload").await?;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+        // Admin routes are not served without proxy
+        let (status, _) = call(&Web::new(), Method::GET, "/pipeline").await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
 
     #[tokio::test]
     async fn not_found() -> Result<()> {
@@ -0,0 +1,262 @@
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::{Error, Result};
+use httpmock::prelude::*;
+use hyper::client::{Client, HttpConnector};
+use hyper_proxy::{Intercept, ProxyConnector};
+use kkowa_proxy_lib::{cache::{Cache, Memory, CACHE_STATUS},
+                      http::{header, StatusCode},
+                      Proxy};
+use portpicker::pick_unused_port;
+
+type ProxyClient = Client<ProxyConnector<HttpConnector>>;
+
+/// Run proxy with in-memory cache, returning client configured to use it.
+async fn run() -> ProxyClient {
+    let addr = SocketAddr::from((
+        [127, 0, 0, 1],
+        pick_unused_port().expect("no port available"),
+    ));
+    let url = format!("http://localhost:{port}", port = addr.port());
+
+    tokio::task::spawn(async move {
+        let cache = Cache::new(Memory::new(1024 * 1024));
+        let proxy = Proxy::new("proxy", Default::default(), vec![], vec![Box::new(cache)]);
+        proxy.run(&addr).await.unwrap()
+    });
+
+    // Give proxy server a moment to bind
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let proxy = hyper_proxy::Proxy::new(Intercept::All, url.parse().unwrap());
+    let proxy_connector = ProxyConnector::from_proxy(HttpConnector::new(), proxy).unwrap();
+
+    Client::builder().build(proxy_connector)
+}
+
+/// Test fresh responses are served from cache.
+#[tokio::test]
+async fn hit() -> Result<(), Error> {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=60")
+            .body("Hello World!");
+    });
+    let client = run().await;
+
+    let res = client.get(server.url("/artifact").parse()?).await?;
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; fwd=miss; stored");
+
+    let res = client.get(server.url("/artifact").parse()?).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; hit");
+    assert!(res.headers().contains_key(header::AGE));
+    assert_eq!(
+        hyper::body::to_bytes(res.into_body()).await?,
+        "Hello World!"
+    );
+
+    mock.assert_hits(1);
+
+    Ok(())
+}
+
+/// Test stale responses are revalidated with conditional requests.
+#[tokio::test]
+async fn revalidate() -> Result<(), Error> {
+    let server = MockServer::start();
+    let not_modified = server.mock(|when, then| {
+        when.method(GET)
+            .path("/artifact")
+            .header("If-None-Match", "\"v1\"");
+        then.status(304).header("Cache-Control", "max-age=60");
+    });
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=0")
+            .header("ETag", "\"v1\"")
+            .body("Hello World!");
+    });
+    let client = run().await;
+
+    client.get(server.url("/artifact").parse()?).await?;
+    let res = client.get(server.url("/artifact").parse()?).await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()[CACHE_STATUS],
+        "kkowa; fwd=stale; fwd-status=304"
+    );
+    assert_eq!(
+        hyper::body::to_bytes(res.into_body()).await?,
+        "Hello World!"
+    );
+    mock.assert_hits(1);
+    not_modified.assert_hits(1);
+
+    // Refreshed entry is fresh now
+    let res = client.get(server.url("/artifact").parse()?).await?;
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; hit");
+
+    Ok(())
+}
+
+/// Test client's own conditional requests get `304 Not Modified` rather than stored response, leaving store as is.
+#[tokio::test]
+async fn conditional() -> Result<(), Error> {
+    let server = MockServer::start();
+    let not_modified = server.mock(|when, then| {
+        when.method(GET)
+            .path("/artifact")
+            .header("If-None-Match", "\"v1\"");
+        then.status(304)
+            .header("Cache-Control", "max-age=60")
+            .header("ETag", "\"v1\"");
+    });
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=0")
+            .header("ETag", "\"v1\"")
+            .body("Hello World!");
+    });
+    let client = run().await;
+
+    client.get(server.url("/artifact").parse()?).await?;
+    let req = hyper::Request::get(server.url("/artifact"))
+        .header(header::IF_NONE_MATCH, "\"v1\"")
+        .body(hyper::Body::empty())?;
+    let res = client.request(req).await?;
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; fwd=miss");
+    assert!(hyper::body::to_bytes(res.into_body()).await?.is_empty());
+    mock.assert_hits(1);
+    not_modified.assert_hits(1);
+
+    // Stored entry is still stale, and revalidated by cache itself
+    let res = client.get(server.url("/artifact").parse()?).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()[CACHE_STATUS],
+        "kkowa; fwd=stale; fwd-status=304"
+    );
+    not_modified.assert_hits(2);
+
+    Ok(())
+}
+
+/// Test `304 Not Modified` for other representation than stored one does not refresh stored one.
+#[tokio::test]
+async fn conditional_other_representation() -> Result<(), Error> {
+    let server = MockServer::start();
+    let mut v1 = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=0")
+            .header("ETag", "\"v1\"")
+            .body("v1");
+    });
+    let client = run().await;
+    client.get(server.url("/artifact").parse()?).await?;
+    v1.delete();
+
+    let not_modified = server.mock(|when, then| {
+        when.method(GET)
+            .path("/artifact")
+            .header("If-None-Match", "\"v2\"");
+        then.status(304)
+            .header("Cache-Control", "max-age=60")
+            .header("ETag", "\"v2\"");
+    });
+    let v2 = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=60")
+            .header("ETag", "\"v2\"")
+            .body("v2");
+    });
+
+    // Client holding v2 is told it is current
+    let req = hyper::Request::get(server.url("/artifact"))
+        .header(header::IF_NONE_MATCH, "\"v2\"")
+        .body(hyper::Body::empty())?;
+    let res = client.request(req).await?;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    not_modified.assert_hits(1);
+
+    // Others are not served stale v1 as fresh
+    let res = client.get(server.url("/artifact").parse()?).await?;
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; fwd=miss; stored");
+    assert_eq!(hyper::body::to_bytes(res.into_body()).await?, "v2");
+    v2.assert_hits(1);
+
+    Ok(())
+}
+
+/// Test variants selected by `Vary` header are stored side by side.
+#[tokio::test]
+async fn vary() -> Result<(), Error> {
+    let server = MockServer::start();
+    let gzip = server.mock(|when, then| {
+        when.method(GET)
+            .path("/artifact")
+            .header("Accept-Encoding", "gzip");
+        then.status(200)
+            .header("Cache-Control", "max-age=60")
+            .header("Vary", "Accept-Encoding")
+            .body("gzip");
+    });
+    let identity = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "max-age=60")
+            .header("Vary", "Accept-Encoding")
+            .body("identity");
+    });
+    let client = run().await;
+    let get = |encoding: Option<&str>| -> Result<hyper::Request<hyper::Body>> {
+        let mut req = hyper::Request::get(server.url("/artifact"));
+        if let Some(encoding) = encoding {
+            req = req.header(header::ACCEPT_ENCODING, encoding);
+        }
+
+        Ok(req.body(hyper::Body::empty())?)
+    };
+
+    client.request(get(Some("gzip"))?).await?;
+    client.request(get(None)?).await?;
+    for (encoding, body) in [(Some("gzip"), "gzip"), (None, "identity")] {
+        let res = client.request(get(encoding)?).await?;
+        assert_eq!(res.headers()[CACHE_STATUS], "kkowa; hit");
+        assert_eq!(hyper::body::to_bytes(res.into_body()).await?, body);
+    }
+    gzip.assert_hits(1);
+    identity.assert_hits(1);
+
+    Ok(())
+}
+
+/// Test `no-store` responses are never cached.
+#[tokio::test]
+async fn no_store() -> Result<(), Error> {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/artifact");
+        then.status(200)
+            .header("Cache-Control", "no-store")
+            .body("Hello World!");
+    });
+    let client = run().await;
+
+    client.get(server.url("/artifact").parse()?).await?;
+    let res = client.get(server.url("/artifact").parse()?).await?;
+
+    assert_eq!(res.headers()[CACHE_STATUS], "kkowa; fwd=miss");
+    mock.assert_hits(2);
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::{Error, Result};
+use httpmock::prelude::*;
+use hyper::client::{Client, HttpConnector};
+use hyper_proxy::{Intercept, ProxyConnector};
+use kkowa_proxy_lib::{http::StatusCode,
+                      vcr::{Matching, Mode, Recorder},
+                      Proxy};
+use portpicker::pick_unused_port;
+
+type ProxyClient = Client<ProxyConnector<HttpConnector>>;
+
+/// Run proxy with recorder handler, returning client configured to use it.
+async fn run(recorder: Arc<Recorder>) -> ProxyClient {
+    let addr = SocketAddr::from((
+        [127, 0, 0, 1],
+        pick_unused_port().expect("no port available"),
+    ));
+    let url = format!("http://localhost:{port}", port = addr.port());
+
+    tokio::task::spawn(async move {
+        let proxy = Proxy::new(
+            "proxy",
+            Default::default(),
+            vec![],
+            vec![Box::new(recorder)],
+        );
+        proxy.run(&addr).await.unwrap()
+    });
+
+    // Give proxy server a moment to bind
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let proxy = hyper_proxy::Proxy::new(Intercept::All, url.parse().unwrap());
+    let proxy_connector = ProxyConnector::from_proxy(HttpConnector::new(), proxy).unwrap();
+
+    Client::builder().build(proxy_connector)
+}
+
+/// Test recorded exchanges are replayed without reaching remote server.
+#[tokio::test]
+async fn record_and_replay() -> Result<(), Error> {
+    let server = MockServer::start();
+    let mut mock = server.mock(|when, then| {
+        when.method(GET).path("/get");
+        then.status(200).body("Hello World!");
+    });
+    let path = std::env::temp_dir().join(format!(
+        "kkowa-vcr-tests-{pid}.json",
+        pid = std::process::id()
+    ));
+
+    // Record
+    let recorder = Arc::new(Recorder::new(&path, Mode::Record, Matching::default())?);
+    let client = run(Arc::clone(&recorder)).await;
+    let res = client.get(server.url("/get").parse()?).await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    mock.assert();
+    mock.delete();
+    recorder.flush()?;
+
+    // Replay
+    let client = run(Arc::new(Recorder::new(
+        &path,
+        Mode::Replay,
+        Matching::builder().strict(true).build()?,
+    )?))
+    .await;
+    let res = client.get(server.url("/get").parse()?).await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        hyper::body::to_bytes(res.into_body()).await?,
+        "Hello World!"
+    );
+
+    let res = client.get(server.url("/unknown").parse()?).await?;
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}